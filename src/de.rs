@@ -1,18 +1,223 @@
+use std::io::Read;
 use serde::de::{self, Deserialize};
-use byteorder::{LittleEndian, ReadBytesExt};
+use serde::de::value::ValueDeserializer;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use flate2::read::GzDecoder;
 use errors::*;
+use tl;
+
+/// Default for the most bytes a `gzip_packed` object may inflate to
+pub const DEFAULT_MAX_INFLATED_LEN: usize = 64 * 1024 * 1024;
 
 pub struct Deserializer<R>
     where R: ReadBytesExt
 {
     reader: R,
+
+    // Presence of the conditional field (`flags.N?T`) being deserialized
+    presence: Option<bool>,
+
+    // The value being deserialized is of a bare type and read without its constructor ID
+    bare: bool,
+
+    // The elements of the vector being deserialized are of a bare type (`vector<%Message>`)
+    bare_elements: bool,
+
+    // Most bytes a `gzip_packed` object may inflate to
+    max_inflated_len: usize,
+
+    // Length of the `Object` being deserialized as given by the field before it
+    // (`body:Object[bytes]`); the rest of the input otherwise
+    object_len: Option<usize>,
 }
 
 impl<R> Deserializer<R>
     where R: ReadBytesExt
 {
     pub fn new(reader: R) -> Self {
-        Deserializer { reader: reader }
+        Deserializer {
+            reader: reader,
+            presence: None,
+            bare: false,
+            bare_elements: false,
+            max_inflated_len: DEFAULT_MAX_INFLATED_LEN,
+            object_len: None,
+        }
+    }
+
+    pub fn max_inflated_len(&self) -> usize {
+        self.max_inflated_len
+    }
+
+    /// Limit the size an object packed with `gzip_packed` may inflate to; a larger
+    /// object fails to deserialize (instead of exhausting memory)
+    pub fn set_max_inflated_len(&mut self, len: usize) {
+        self.max_inflated_len = len;
+    }
+
+    /// Read a length-prefixed byte string as written by `Serializer::serialize_bytes`
+    fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let (header_len, len) = match self.reader.read_u8()? {
+            // If L >= 254, the length is in the following 3 bytes; a shorter length is
+            // only ever written in a single byte
+            254 => {
+                let len = self.reader.read_uint::<LittleEndian>(3)? as usize;
+                if len < 254 {
+                    let message = format!("non-canonical length {} for bytes", len);
+                    return Err(de::Error::custom(message));
+                }
+
+                (4, len)
+            }
+
+            255 => return Err(de::Error::custom("invalid length prefix 255 for bytes")),
+            len => (1, len as usize),
+        };
+
+        let mut buffer = vec![0; len];
+        self.reader.read_exact(&mut buffer)?;
+
+        // Skip the padding to a multiple of 4 bytes; the length prefix counts towards it
+        let rem = (header_len + len) % 4;
+        if rem > 0 {
+            for _ in 0..(4 - rem) {
+                let byte = self.reader.read_u8()?;
+                if byte != 0 {
+                    return Err(ErrorKind::InvalidPadding(byte).into());
+                }
+            }
+        }
+
+        Ok(buffer)
+    }
+
+    /// Deserialize the fields of a struct (or struct variant) in order; the flags words
+    /// (`flags:#`) are read as declared and decide which conditional fields are present and
+    /// the length fields (`bytes` before `body:Object[bytes]`) bound the `Object` after them
+    fn deserialize_fields<V: de::Visitor>(&mut self,
+                                          fields: &'static [&'static str],
+                                          visitor: V)
+                                          -> Result<V::Value> {
+        struct FieldsVisitor<'a, R: ReadBytesExt + 'a> {
+            de: &'a mut Deserializer<R>,
+            fields: &'static [&'static str],
+            flags: Vec<(&'static str, u32)>,
+            lengths: Vec<(&'static str, i32)>,
+        }
+
+        impl<'a, R: ReadBytesExt + 'a> de::SeqVisitor for FieldsVisitor<'a, R> {
+            type Error = Error;
+
+            fn visit_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+                where T: de::DeserializeSeed
+            {
+                let (key, fields) = match self.fields.split_first() {
+                    Some((key, fields)) => (*key, fields),
+                    None => return Ok(None),
+                };
+
+                self.fields = fields;
+
+                let field = tl::Field::parse(key);
+                for word in &field.flags {
+                    let flags = self.de.reader.read_u32::<LittleEndian>()?;
+                    self.flags.push((word, flags));
+                }
+
+                let is_length = fields.iter()
+                    .any(|next| tl::Field::parse(next).length == Some(field.name));
+                if is_length {
+                    // The length is kept for the `Object` it bounds
+                    let len = self.de.reader.read_i32::<LittleEndian>()?;
+                    self.lengths.push((field.name, len));
+
+                    let mut buffer = [0; 4];
+                    LittleEndian::write_i32(&mut buffer, len);
+
+                    let value = de::DeserializeSeed::deserialize(seed, &mut Deserializer::new(&buffer[..]))?;
+                    return Ok(Some(value));
+                }
+
+                if let Some((word, bit)) = field.condition {
+                    let flags = match self.flags.iter().find(|flags| flags.0 == word) {
+                        Some(flags) => flags.1,
+                        None => {
+                            return Err(de::Error::custom(format!("no flags word `{}` for {}", word, key)))
+                        }
+                    };
+
+                    self.de.presence = Some(flags & (1 << bit) != 0);
+                }
+
+                if let Some(length) = field.length {
+                    let len = match self.lengths.iter().find(|len| len.0 == length) {
+                        Some(len) => len.1,
+                        None => {
+                            return Err(de::Error::custom(format!("no length field `{}` for {}", length, key)))
+                        }
+                    };
+
+                    // An object is a whole number of 32-bit words
+                    if len < 0 || len % 4 != 0 {
+                        return Err(de::Error::custom(format!("invalid object length {}", len)));
+                    }
+
+                    self.de.object_len = Some(len as usize);
+                }
+
+                self.de.bare = field.bare;
+                self.de.bare_elements = field.bare_elements;
+
+                let value = de::DeserializeSeed::deserialize(seed, &mut *self.de);
+                self.de.presence = None;
+                self.de.bare = false;
+                self.de.bare_elements = false;
+                self.de.object_len = None;
+
+                Ok(Some(value?))
+            }
+        }
+
+        visitor.visit_seq(FieldsVisitor {
+            de: self,
+            fields: fields,
+            flags: Vec::new(),
+            lengths: Vec::new(),
+        })
+    }
+
+    /// Read what follows the constructor ID of a `gzip_packed` and deserialize the object
+    /// it inflates to in its place
+    fn deserialize_packed<T, F>(&mut self, deserialize: F) -> Result<T>
+        where F: FnOnce(&mut Deserializer<&[u8]>) -> Result<T>
+    {
+        let data = inflate(&self.read_bytes()?, self.max_inflated_len)?;
+
+        let mut de = Deserializer::new(&data[..]);
+        de.max_inflated_len = self.max_inflated_len;
+
+        let value = deserialize(&mut de)?;
+        if !de.reader.is_empty() {
+            return Err(ErrorKind::InvalidMessageLength(data.len(), data.len() - de.reader.len())
+                .into());
+        }
+
+        Ok(value)
+    }
+
+    /// Check a constructor ID against the one carried in the name of a boxed type
+    /// (`predicate#id`)
+    fn check_constructor_id(&self, name: &'static str, id: u32) -> Result<()> {
+        let expected = match tl::constructor_id(name) {
+            Some(id) => id,
+            None => return Err(de::Error::custom(format!("no constructor ID for {}", name))),
+        };
+
+        if id != expected {
+            return Err(ErrorKind::UnknownConstructor(name, id).into());
+        }
+
+        Ok(())
     }
 }
 
@@ -28,8 +233,17 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
 
     #[inline]
     fn deserialize_bool<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_bool"))
+        if let Some(present) = self.presence.take() {
+            // A conditional field of type `true` (`flags.N?true`) is only the bit in the flags
+            return visitor.visit_bool(present);
+        }
+
+        // Bool is the boxed boolTrue or boolFalse
+        match self.reader.read_u32::<LittleEndian>()? {
+            tl::BOOL_TRUE => visitor.visit_bool(true),
+            tl::BOOL_FALSE => visitor.visit_bool(false),
+            id => Err(ErrorKind::UnknownConstructor("Bool", id).into()),
+        }
     }
 
     #[inline]
@@ -100,30 +314,37 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
 
     #[inline]
     fn deserialize_str<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        unimplemented!();
+        self.deserialize_string(visitor)
     }
 
     #[inline]
     fn deserialize_string<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        unimplemented!();
+        // A string is serialized as its UTF-8 bytes
+        let value = String::from_utf8(self.read_bytes()?)?;
+        visitor.visit_string(value)
     }
 
     #[inline]
     fn deserialize_bytes<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_bytes"))
+        self.deserialize_byte_buf(visitor)
     }
 
     #[inline]
     fn deserialize_byte_buf<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_byte_buf"))
+        let value = self.read_bytes()?;
+        visitor.visit_byte_buf(value)
     }
 
     #[inline]
     fn deserialize_option<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_option"))
+        // A conditional field (`flags.N?T`) is present if its bit is set in the flags
+        match self.presence.take() {
+            Some(true) => visitor.visit_some(self),
+            Some(false) => visitor.visit_none(),
+
+            // NOTE: Telegram has no representation for this outside of a conditional field.
+            None => Err(de::Error::custom("Telegram does not support Deserializer::deserialize_option")),
+        }
     }
 
     #[inline]
@@ -137,8 +358,21 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
                                                name: &'static str,
                                                visitor: V)
                                                -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_unit_struct"))
+        // A constructor without parameters is only its constructor ID; nothing at all if bare
+        if self.bare {
+            self.bare = false;
+        } else {
+            let id = self.reader.read_u32::<LittleEndian>()?;
+            if id == tl::GZIP_PACKED {
+                return self.deserialize_packed(|de| {
+                    de::Deserializer::deserialize_unit_struct(de, name, visitor)
+                });
+            }
+
+            self.check_constructor_id(name, id)?;
+        }
+
+        visitor.visit_unit()
     }
 
     #[inline]
@@ -146,13 +380,113 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
                                                   name: &'static str,
                                                   visitor: V)
                                                   -> Result<V::Value> {
-        // NOTE: Telegram has no representation for this.
-        Err(de::Error::custom("Telegram does not support Deserializer::deserialize_newtype_struct"))
+        if name == tl::OBJECT {
+            // An object of any type is as long as the field before it says (`message`) or
+            // the rest of the input (`rpc_result`)
+            let mut buffer = Vec::new();
+            match self.object_len.take() {
+                Some(len) => {
+                    (&mut self.reader).take(len as u64).read_to_end(&mut buffer)?;
+                    if buffer.len() != len {
+                        return Err(ErrorKind::InvalidMessageLength(len, buffer.len()).into());
+                    }
+                }
+
+                None => {
+                    self.reader.read_to_end(&mut buffer)?;
+                }
+            }
+
+            if buffer.len() >= 4 && LittleEndian::read_u32(&buffer) == tl::GZIP_PACKED {
+                // The packed object is kept inflated
+                let inflated = {
+                    let mut de = Deserializer::new(&buffer[4..]);
+                    de.max_inflated_len = self.max_inflated_len;
+
+                    let inflated = de.deserialize_packed(|de| {
+                            let mut inflated = Vec::new();
+                            de.reader.read_to_end(&mut inflated)?;
+                            Ok(inflated)
+                        })?;
+
+                    if !de.reader.is_empty() {
+                        return Err(ErrorKind::InvalidMessageLength(buffer.len() - de.reader.len(),
+                                                                   buffer.len())
+                            .into());
+                    }
+
+                    inflated
+                };
+
+                buffer = inflated;
+            }
+
+            return visitor.visit_byte_buf(buffer);
+        }
+
+        // A newtype is transparent; it is read as the value it wraps
+        visitor.visit_newtype_struct(self)
     }
 
     #[inline]
     fn deserialize_seq<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
-        unimplemented!();
+        struct SeqVisitor<'a, R: ReadBytesExt + 'a> {
+            de: &'a mut Deserializer<R>,
+            len: usize,
+            bare_elements: bool,
+        }
+
+        impl<'a, R: ReadBytesExt + 'a> de::SeqVisitor for SeqVisitor<'a, R> {
+            type Error = Error;
+
+            fn visit_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+                where T: de::DeserializeSeed
+            {
+                if self.len == 0 {
+                    return Ok(None);
+                }
+
+                self.len -= 1;
+                self.de.bare = self.bare_elements;
+
+                let value = de::DeserializeSeed::deserialize(seed, &mut *self.de);
+                self.de.bare = false;
+
+                Ok(Some(value?))
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.len, Some(self.len))
+            }
+        }
+
+        // A boxed Vector starts with its constructor ID; a bare vector with the length
+        if !self.bare {
+            let id = self.reader.read_u32::<LittleEndian>()?;
+            if id == tl::GZIP_PACKED {
+                return self.deserialize_packed(|de| de::Deserializer::deserialize_seq(de, visitor));
+            }
+
+            if id != tl::VECTOR {
+                return Err(ErrorKind::UnknownConstructor("Vector", id).into());
+            }
+        }
+
+        self.bare = false;
+
+        let len = self.reader.read_i32::<LittleEndian>()?;
+        if len < 0 {
+            return Err(de::Error::custom(format!("invalid vector length {}", len)));
+        }
+
+        let bare_elements = self.bare_elements;
+        self.bare_elements = false;
+
+        visitor.visit_seq(SeqVisitor {
+            de: self,
+            len: len as usize,
+            bare_elements: bare_elements,
+        })
     }
 
     #[inline]
@@ -160,7 +494,8 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
                                                   len: usize,
                                                   visitor: V)
                                                   -> Result<V::Value> {
-        unimplemented!();
+        // A fixed size sequence is read as its elements; like a tuple
+        self.deserialize_tuple(len, visitor)
     }
 
     #[inline]
@@ -199,11 +534,25 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
 
     #[inline]
     fn deserialize_struct<V: de::Visitor>(self,
-                                          _: &'static str,
+                                          name: &'static str,
                                           fields: &'static [&'static str],
                                           visitor: V)
                                           -> Result<V::Value> {
-        self.deserialize_tuple(fields.len(), visitor)
+        // A struct without a constructor ID, or of a bare type (`%Message`), is deserialized bare
+        if !self.bare && tl::constructor_id(name).is_some() {
+            let id = self.reader.read_u32::<LittleEndian>()?;
+            if id == tl::GZIP_PACKED {
+                return self.deserialize_packed(|de| {
+                    de::Deserializer::deserialize_struct(de, name, fields, visitor)
+                });
+            }
+
+            self.check_constructor_id(name, id)?;
+        }
+
+        self.bare = false;
+
+        self.deserialize_fields(fields, visitor)
     }
 
     #[inline]
@@ -217,7 +566,73 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
                                         variants: &'static [&'static str],
                                         visitor: V)
                                         -> Result<V::Value> {
-        unimplemented!();
+        struct EnumVisitor<'a, R: ReadBytesExt + 'a> {
+            de: &'a mut Deserializer<R>,
+            index: u32,
+        }
+
+        impl<'a, R: ReadBytesExt + 'a> de::EnumVisitor for EnumVisitor<'a, R> {
+            type Error = Error;
+            type Variant = Self;
+
+            fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+                where V: de::DeserializeSeed
+            {
+                // Variants are identified by their index
+                let variant = ValueDeserializer::<Error>::into_deserializer(self.index);
+                let value = de::DeserializeSeed::deserialize(seed, variant)?;
+
+                Ok((value, self))
+            }
+        }
+
+        impl<'a, R: ReadBytesExt + 'a> de::VariantVisitor for EnumVisitor<'a, R> {
+            type Error = Error;
+
+            fn visit_unit(self) -> Result<()> {
+                // The constructor ID was the whole value
+                Ok(())
+            }
+
+            fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value>
+                where T: de::DeserializeSeed
+            {
+                de::DeserializeSeed::deserialize(seed, &mut *self.de)
+            }
+
+            fn visit_tuple<V: de::Visitor>(self, len: usize, visitor: V) -> Result<V::Value> {
+                de::Deserializer::deserialize_tuple(&mut *self.de, len, visitor)
+            }
+
+            fn visit_struct<V: de::Visitor>(self,
+                                            fields: &'static [&'static str],
+                                            visitor: V)
+                                            -> Result<V::Value> {
+                // The constructor ID was already consumed to pick the variant
+                self.de.deserialize_fields(fields, visitor)
+            }
+        }
+
+        // Pick the variant by the leading constructor ID
+        let id = self.reader.read_u32::<LittleEndian>()?;
+        if id == tl::GZIP_PACKED {
+            return self.deserialize_packed(|de| {
+                de::Deserializer::deserialize_enum(de, name, variants, visitor)
+            });
+        }
+
+        let index = variants.iter().position(|variant| tl::constructor_id(variant) == Some(id));
+
+        match index {
+            Some(index) => {
+                visitor.visit_enum(EnumVisitor {
+                    de: self,
+                    index: index as u32,
+                })
+            }
+
+            None => Err(ErrorKind::UnknownConstructor(name, id).into()),
+        }
     }
 
     #[inline]
@@ -226,3 +641,174 @@ impl<'a, R> de::Deserializer for &'a mut Deserializer<R>
         Err(de::Error::custom("Telegram does not support Deserializer::deserialize_ignored_any"))
     }
 }
+
+/// Inflate the `packed_data` of a `gzip_packed`; at most `max_len` bytes
+fn inflate(data: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(data)?.take(max_len as u64 + 1).read_to_end(&mut inflated)?;
+
+    if inflated.len() > max_len {
+        return Err(ErrorKind::InflatedTooLong(max_len).into());
+    }
+
+    Ok(inflated)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use flate2;
+    use flate2::write::GzEncoder;
+    use serde::{Serialize, Deserialize};
+    use serde::bytes::Bytes;
+    use errors::*;
+    use ser::Serializer;
+    use tl::{self, Object};
+    use mtproto::schema;
+    use super::Deserializer;
+
+    fn gzip_packed(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::Default);
+        encoder.write_all(data).unwrap();
+
+        let mut packed = Vec::new();
+        packed.write_u32::<LittleEndian>(tl::GZIP_PACKED).unwrap();
+        let packed_data = encoder.finish().unwrap();
+        Bytes::new(&packed_data).serialize(&mut Serializer::new(&mut packed)).unwrap();
+
+        packed
+    }
+
+    fn vector_of_longs(len: usize) -> (Vec<i64>, Vec<u8>) {
+        let values = (0..len as i64).collect::<Vec<_>>();
+
+        let mut data = Vec::new();
+        values.serialize(&mut Serializer::new(&mut data)).unwrap();
+
+        (values, data)
+    }
+
+    #[test]
+    fn inflate_vector() {
+        let (values, data) = vector_of_longs(100);
+        let packed = gzip_packed(&data);
+
+        let mut de = Deserializer::new(&packed[..]);
+        assert_eq!(Vec::<i64>::deserialize(&mut de).unwrap(), values);
+    }
+
+    #[test]
+    fn reject_inflated_too_long() {
+        let (_, data) = vector_of_longs(100);
+        let packed = gzip_packed(&data);
+
+        let mut de = Deserializer::new(&packed[..]);
+        de.set_max_inflated_len(data.len() - 1);
+        match Vec::<i64>::deserialize(&mut de) {
+            Err(Error(ErrorKind::InflatedTooLong(len), _)) => assert_eq!(len, data.len() - 1),
+            result => panic!("expected InflatedTooLong; found {:?}", result),
+        }
+
+        // Up to the limit
+        let mut de = Deserializer::new(&packed[..]);
+        de.set_max_inflated_len(data.len());
+        assert_eq!(Vec::<i64>::deserialize(&mut de).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn reject_packed_bool() {
+        let packed = gzip_packed(&[0xb5, 0x75, 0x72, 0x99]);
+
+        match bool::deserialize(&mut Deserializer::new(&packed[..])) {
+            Err(Error(ErrorKind::UnknownConstructor("Bool", tl::GZIP_PACKED), _)) => {}
+            result => panic!("expected UnknownConstructor; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reject_non_canonical_len() {
+        for &len in &[0, 1, 253] {
+            let mut data = vec![254, len, 0, 0];
+            data.extend(vec![0x61; len as usize]);
+            data.extend(vec![0; (4 - len as usize % 4) % 4]);
+
+            match String::deserialize(&mut Deserializer::new(&data[..])) {
+                Err(Error(ErrorKind::Msg(message), _)) => {
+                    assert_eq!(message, format!("non-canonical length {} for bytes", len))
+                }
+                result => panic!("expected a non-canonical length; found {:?}", result),
+            }
+        }
+
+        // The long form from 254 on
+        let mut data = vec![254, 254, 0, 0];
+        data.extend(vec![0x61; 254]);
+        data.extend(vec![0; 2]);
+        assert_eq!(String::deserialize(&mut Deserializer::new(&data[..])).unwrap(),
+                   "a".repeat(254));
+    }
+
+    #[test]
+    fn reject_padding() {
+        let data = [3, 0x61, 0x62, 0x63];
+        assert_eq!(String::deserialize(&mut Deserializer::new(&data[..])).unwrap(), "abc");
+
+        let data = [2, 0x61, 0x62, 0x01];
+        match String::deserialize(&mut Deserializer::new(&data[..])) {
+            Err(Error(ErrorKind::InvalidPadding(1), _)) => {}
+            result => panic!("expected InvalidPadding; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_utf8() {
+        let data = [2, 0xc3, 0x28, 0x00];
+        match String::deserialize(&mut Deserializer::new(&data[..])) {
+            Err(Error(ErrorKind::Utf8(..), _)) => {}
+            result => panic!("expected Utf8; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn dispatch_enum() {
+        // bad_server_salt#edab447b bad_msg_id:long bad_msg_seqno:int error_code:int
+        //                          new_server_salt:long
+        let buffer = [0x7b, 0x44, 0xab, 0xed, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                      0x00, 0x03, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00, 0xef, 0xcd,
+                      0xab, 0x89, 0x67, 0x45, 0x23, 0x01];
+
+        match Object(buffer.to_vec()).decode::<schema::BadMsgNotification>().unwrap() {
+            schema::BadMsgNotification::bad_server_salt { bad_msg_id,
+                                                          bad_msg_seqno,
+                                                          error_code,
+                                                          new_server_salt } => {
+                assert_eq!((bad_msg_id, bad_msg_seqno, error_code, new_server_salt),
+                           (8, 3, 48, 0x0123456789abcdef))
+            }
+            notification => panic!("expected bad_server_salt; found {:?}", notification),
+        }
+
+        // bad_msg_notification#a7eff811 bad_msg_id:long bad_msg_seqno:int error_code:int
+        let buffer = [0x11, 0xf8, 0xef, 0xa7, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                      0x00, 0x03, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00];
+
+        match Object(buffer.to_vec()).decode::<schema::BadMsgNotification>().unwrap() {
+            schema::BadMsgNotification::bad_msg_notification { bad_msg_id: 8,
+                                                               bad_msg_seqno: 3,
+                                                               error_code: 16 } => {}
+            notification => panic!("expected bad_msg_notification; found {:?}", notification),
+        }
+    }
+
+    #[test]
+    fn reject_unknown_constructor() {
+        let buffer = [0x44, 0x33, 0x22, 0x11, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                      0x00, 0x03, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00];
+
+        match Object(buffer.to_vec()).decode::<schema::BadMsgNotification>() {
+            Err(Error(ErrorKind::UnknownConstructor("BadMsgNotification", 0x11223344), _)) => {}
+            result => panic!("expected UnknownConstructor; found {:?}", result),
+        }
+    }
+}
@@ -1,10 +1,196 @@
-use std::fmt::Display;
+use std::fmt::{self, Display};
 use serde::{ser, de};
 
 error_chain! {
     foreign_links {
         SystemTime(::std::time::SystemTimeError);
         Io(::std::io::Error);
+        Utf8(::std::string::FromUtf8Error);
+    }
+
+    errors {
+        UnknownConstructor(name: &'static str, id: u32) {
+            description("unknown constructor")
+            display("unknown constructor 0x{:08x} for {}", id, name)
+        }
+
+        InvalidPadding(byte: u8) {
+            description("non-zero padding")
+            display("non-zero padding byte 0x{:02x} after string or bytes", byte)
+        }
+
+        InvalidMessageLength(expected: usize, found: usize) {
+            description("invalid message length")
+            display("invalid message length; expected {} bytes but found {}", expected, found)
+        }
+
+        UnexpectedAuthKeyId(id: i64) {
+            description("unexpected auth_key_id")
+            display("unexpected auth_key_id {:016x}", id)
+        }
+
+        UnexpectedSessionId(id: i64) {
+            description("unexpected session_id")
+            display("unexpected session_id {:016x}", id)
+        }
+
+        InvalidMsgKey {
+            description("msg_key does not match the decrypted message")
+            display("msg_key does not match the decrypted message")
+        }
+
+        InvalidPaddingLength(len: usize) {
+            description("invalid padding length")
+            display("invalid padding length of {} bytes after the message", len)
+        }
+
+        InvalidBlockLength(len: usize) {
+            description("data is not a multiple of the block size")
+            display("length of {} bytes is not a multiple of the AES block size", len)
+        }
+
+        InvalidPacketLength(len: usize) {
+            description("invalid packet length")
+            display("invalid packet length of {} bytes", len)
+        }
+
+        InvalidChecksum(expected: u32, found: u32) {
+            description("packet checksum mismatch")
+            display("packet checksum mismatch; expected 0x{:08x} but found 0x{:08x}", expected, found)
+        }
+
+        UnexpectedSeqNo(expected: i32, found: i32) {
+            description("unexpected packet sequence number")
+            display("unexpected packet sequence number; expected {} but found {}", expected, found)
+        }
+
+        InvalidHttpResponse(reason: &'static str) {
+            description("invalid HTTP response")
+            display("invalid HTTP response: {}", reason)
+        }
+
+        HttpStatus(status: u16) {
+            description("unexpected HTTP status")
+            display("unexpected HTTP status {}", status)
+        }
+
+        NoPendingRequest {
+            description("no request to receive a response to")
+            display("no request to receive a response to")
+        }
+
+        TransportError(code: i32) {
+            description("transport error")
+            display("transport error {}", code)
+        }
+
+        AuthKeyGeneration(reason: &'static str) {
+            description("authorization key generation failed")
+            display("authorization key generation failed: {}", reason)
+        }
+
+        InflatedTooLong(max_len: usize) {
+            description("packed object inflates to too many bytes")
+            display("packed object inflates to more than {} bytes", max_len)
+        }
+
+        InvalidContainerLength(len: usize) {
+            description("invalid number of messages in a container")
+            display("invalid number of messages in a container: {}", len)
+        }
+
+        UnexpectedReqMsgId(id: i64) {
+            description("result for a message that is not a pending call")
+            display("result for message {} that is not a pending call", id)
+        }
+
+        Rpc(code: i32, error: RpcError) {
+            description("call failed")
+            display("call failed with {}: {}", code, error)
+        }
+    }
+}
+
+/// Error answered to a call (`rpc_error`); parsed from its message with the numeric
+/// argument some of them end with (`FLOOD_WAIT_30`)
+// https://core.telegram.org/api/errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    /// Wait this many seconds before repeating the call
+    FloodWait(u32),
+
+    /// The phone number is registered with another data center
+    PhoneMigrate(u32),
+
+    /// The file is stored in another data center
+    FileMigrate(u32),
+
+    /// Repeat the call through another data center
+    NetworkMigrate(u32),
+
+    /// The user is associated with another data center
+    UserMigrate(u32),
+
+    /// The account has two-step verification; `auth.checkPassword` is needed to sign in
+    SessionPasswordNeeded,
+
+    /// The authorization key is not (or no longer) bound to a user
+    AuthKeyUnregistered,
+
+    PhoneNumberInvalid,
+    PhoneCodeInvalid,
+    PhoneCodeExpired,
+
+    /// Any other message
+    Other(String),
+}
+
+impl RpcError {
+    pub fn parse(message: &str) -> RpcError {
+        // FLOOD_WAIT_30 => (FLOOD_WAIT, 30)
+        let (name, argument) = match message.rfind('_') {
+            Some(index) => {
+                match message[index + 1..].parse::<u32>() {
+                    Ok(argument) => (&message[..index], Some(argument)),
+                    Err(_) => (message, None),
+                }
+            }
+
+            None => (message, None),
+        };
+
+        match (name, argument) {
+            ("FLOOD_WAIT", Some(seconds)) => RpcError::FloodWait(seconds),
+            ("PHONE_MIGRATE", Some(dc)) => RpcError::PhoneMigrate(dc),
+            ("FILE_MIGRATE", Some(dc)) => RpcError::FileMigrate(dc),
+            ("NETWORK_MIGRATE", Some(dc)) => RpcError::NetworkMigrate(dc),
+            ("USER_MIGRATE", Some(dc)) => RpcError::UserMigrate(dc),
+            ("SESSION_PASSWORD_NEEDED", None) => RpcError::SessionPasswordNeeded,
+            ("AUTH_KEY_UNREGISTERED", None) => RpcError::AuthKeyUnregistered,
+            ("PHONE_NUMBER_INVALID", None) => RpcError::PhoneNumberInvalid,
+            ("PHONE_CODE_INVALID", None) => RpcError::PhoneCodeInvalid,
+            ("PHONE_CODE_EXPIRED", None) => RpcError::PhoneCodeExpired,
+            _ => RpcError::Other(message.to_string()),
+        }
+    }
+}
+
+impl Display for RpcError {
+    /// Format the error as the message it was parsed from
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RpcError::FloodWait(seconds) => write!(f, "FLOOD_WAIT_{}", seconds),
+            RpcError::PhoneMigrate(dc) => write!(f, "PHONE_MIGRATE_{}", dc),
+            RpcError::FileMigrate(dc) => write!(f, "FILE_MIGRATE_{}", dc),
+            RpcError::NetworkMigrate(dc) => write!(f, "NETWORK_MIGRATE_{}", dc),
+            RpcError::UserMigrate(dc) => write!(f, "USER_MIGRATE_{}", dc),
+            RpcError::SessionPasswordNeeded => f.write_str("SESSION_PASSWORD_NEEDED"),
+            RpcError::AuthKeyUnregistered => f.write_str("AUTH_KEY_UNREGISTERED"),
+            RpcError::PhoneNumberInvalid => f.write_str("PHONE_NUMBER_INVALID"),
+            RpcError::PhoneCodeInvalid => f.write_str("PHONE_CODE_INVALID"),
+            RpcError::PhoneCodeExpired => f.write_str("PHONE_CODE_EXPIRED"),
+            RpcError::Other(ref message) => f.write_str(message),
+        }
     }
 }
 
@@ -19,3 +205,30 @@ impl de::Error for Error {
         Error::from(msg.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RpcError;
+
+    #[test]
+    fn parse_rpc_errors() {
+        assert_eq!(RpcError::parse("FLOOD_WAIT_30"), RpcError::FloodWait(30));
+        assert_eq!(RpcError::parse("PHONE_MIGRATE_4"), RpcError::PhoneMigrate(4));
+        assert_eq!(RpcError::parse("SESSION_PASSWORD_NEEDED"),
+                   RpcError::SessionPasswordNeeded);
+        assert_eq!(RpcError::parse("CHAT_ADMIN_REQUIRED"),
+                   RpcError::Other("CHAT_ADMIN_REQUIRED".to_string()));
+
+        // The argument is missing or not a number
+        assert_eq!(RpcError::parse("FLOOD_WAIT_X"), RpcError::Other("FLOOD_WAIT_X".to_string()));
+        assert_eq!(RpcError::parse("PHONE_MIGRATE"), RpcError::Other("PHONE_MIGRATE".to_string()));
+    }
+
+    #[test]
+    fn display_rpc_errors() {
+        let messages = ["FLOOD_WAIT_30", "PHONE_MIGRATE_4", "SESSION_PASSWORD_NEEDED", "CHAT_ADMIN_REQUIRED"];
+        for message in &messages {
+            assert_eq!(RpcError::parse(message).to_string(), *message);
+        }
+    }
+}
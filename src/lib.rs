@@ -1,6 +1,11 @@
 #![feature(i128_type)]
 
 extern crate byteorder;
+extern crate crc;
+extern crate crypto;
+extern crate flate2;
+extern crate num_bigint;
+extern crate rand;
 extern crate serde;
 
 #[macro_use]
@@ -9,7 +14,13 @@ extern crate serde_derive;
 #[macro_use]
 extern crate error_chain;
 
+#[macro_use]
+extern crate log;
+
 pub mod ser;
 pub mod de;
 pub mod errors;
+pub mod mtproto;
 pub mod schema;
+pub mod tl;
+pub mod transport;
@@ -6,7 +6,6 @@
 // #[macro_use]
 // extern crate serde_derive;
 
-// use std::mem::size_of;
 // use std::io::Cursor;
 // use serde::{Serialize, Deserialize, Deserializer};
 // use serde::ser::{SerializeStruct, SerializeTuple};
@@ -20,12 +19,9 @@ extern crate telegram_codegen;
 
 fn main() {
     telegram_codegen::translate("schema.json", "schema.rs").unwrap();
+    telegram_codegen::translate("mtproto-schema.json", "src/mtproto/schema.rs").unwrap();
 }
 
-// // Path: /api
-// // [..] they are made into a payload which is followed by a POST request to the URL/api [..]
-// // https://core.telegram.org/mtproto#http-transport
-
 // // #[derive(Serialize, Deserialize, Debug, Clone)]
 // // #[id(85337187)]
 // struct ResPQ {
@@ -44,55 +40,16 @@ fn main() {
 
 //     let data = req_pq { nonce: nonce };
 
-//     // A message is ::
-//     //  auth_key_id: u64
-//     //  message_id: u64
-//     //  message_length: u32
-//     //  data: ...
-
-//     let mut body = Vec::<u8>::new();
-
-//     // Key Identifier
-//     // [auth_key_id: u64]
-//     0u64.serialize(&mut body);
-
 //     // Compute message identifier
-//     let now_d = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
-//     let now_s = now_d.as_secs();
-//     let message_id = ((now_s as u64) << 32) + (now_d.subsec_nanos() as u64);
-
-//     // Message Identifier
-//     // [message_id: u64]
-//     message_id.serialize(&mut body);
-
-//     // Compute message length
-//     // <identifier> (4) + <data size>
-//     let message_data_length = 4 + (size_of::<req_pq>()) as u32;
-
-//     // Message Length
-//     message_data_length.serialize(&mut body);
-
-//     // Message Data
-
-//     // Identifier
-//     1615239032i32.serialize(&mut body);
-
-//     // ***
-//     data.nonce.serialize(&mut body);
+//     let message_id = telegram::mtproto::msg_id::MessageIdGenerator::new().next_id();
 
-//     let client = Client::new();
-//     let mut res = client.post("http://149.154.167.50:443/api")
-//         .header(Connection::keep_alive())
-//         .header(ContentLength(body.len() as u64))
-//         .body(Body::BufBody(&body, body.len()))
-//         .send()?;
+//     // auth_key_id (0), message_id, message_data_length and the serialized request
+//     let body = telegram::mtproto::plain::Message::new(message_id, data).to_vec()?;
 
-//     println!("status: {}", res.status);
-//     println!("headers: {}", res.headers);
-//     println!("\n-----\n{:?}", res);
+//     let mut transport = telegram::transport::http::Http::connect("149.154.167.50:80")?;
+//     transport.send(&body)?;
 
-//     let mut res_body = Vec::new();
-//     res.read_to_end(&mut res_body)?;
+//     let res_body = transport.receive()?;
 
 //     let mut f = File::create("foo.txt")?;
 //     f.write_all(&res_body)?;
@@ -0,0 +1,849 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use num_bigint::BigUint;
+use rand::{self, Rng};
+use serde::bytes::ByteBuf;
+use serde::{Serialize, Deserialize};
+use errors::*;
+use de::Deserializer;
+use tl::{Int128, Int256, Object};
+use super::ige::AesIge;
+use super::msg_id::{Clock, MessageIdGenerator, SystemClock};
+use super::plain::Message;
+use super::rsa::RsaPublicKey;
+use super::schema::{ReqPq, ResPQ, ReqDHParams, P_Q_inner_data, Server_DH_Params,
+                    Server_DH_inner_data, SetClientDHParams, Client_DH_Inner_Data,
+                    Set_client_DH_params_answer};
+use super::sha1;
+
+/// Length in bits of the DH prime (and so of the authorization key)
+const DH_PRIME_BITS: usize = 2048;
+
+/// The safe prime the servers use for `dh_prime` (big-endian); any other prime is checked
+/// to be a safe prime before it is used
+const KNOWN_DH_PRIME: &'static str = "c71caeb9c6b1c9048e6c522f70f13f73980d40238e3e21c14934d037563d930f\
+                                      48198a0aa7c14058229493d22530f4dbfa336f6e0ac925139543aed44cce7c37\
+                                      20fd51f69458705ac68cd4fe6b6b13abdc9746512969328454f18faf8c595f64\
+                                      2477fe96bb2a941d5bcd1d4ac8cc49880708fa9b378e3c4f3a9060bee67cf9a4\
+                                      a4a695811051907e162753b56b0f6b410dba74d8a84b2a14b3144e0ef1284754\
+                                      fd17ed950d5965b4b9dd46582db1178d169c6bc465b0d6ff9ca3928fef5b9ae4\
+                                      e418fc15e83ebea0f87fa9ff5eed70050ded2849f47bf959d956850ce929851f\
+                                      0d8115f635b105ee2e4e15d04b2454bf6f4fadf034b10403119cd8e3b92fcc5b";
+
+/// Rounds of the Miller-Rabin test; the chance of taking a composite for a prime is
+/// at most 4^-rounds
+const MILLER_RABIN_ROUNDS: usize = 32;
+
+/// An authorization key shared with the server
+#[derive(Clone)]
+pub struct AuthKey {
+    key: [u8; 256],
+    id: i64,
+    aux_hash: i64,
+}
+
+impl AuthKey {
+    pub fn new(key: [u8; 256]) -> AuthKey {
+        let hash = sha1(&[&key]);
+
+        AuthKey {
+            key: key,
+            // The lower 64 bits of the SHA1 of the key
+            id: LittleEndian::read_i64(&hash[12..20]),
+            // The higher 64 bits of the SHA1 of the key
+            aux_hash: LittleEndian::read_i64(&hash[..8]),
+        }
+    }
+
+    pub fn key(&self) -> &[u8; 256] {
+        &self.key
+    }
+
+    /// The `auth_key_id` sent with every message encrypted with this key
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+}
+
+/// Result of the creation of an authorization key
+#[derive(Clone)]
+pub struct Authorization {
+    pub auth_key: AuthKey,
+
+    /// Salt to use for the first messages encrypted with the key
+    pub server_salt: i64,
+
+    /// Difference in seconds between the server time and the local time
+    pub time_offset: i32,
+}
+
+/// Next step of the creation of an authorization key
+pub enum Step {
+    /// Send the message and pass the answer of the server to `Handshake::handle`
+    Send(Vec<u8>),
+
+    /// The authorization key was created
+    Done(Authorization),
+}
+
+enum State {
+    /// `req_pq` is sent; waiting on `ResPQ`
+    ResPQ { nonce: Int128 },
+
+    /// `req_DH_params` is sent; waiting on `Server_DH_Params`
+    ServerDHParams {
+        nonce: Int128,
+        server_nonce: Int128,
+        new_nonce: Int256,
+    },
+
+    /// `set_client_DH_params` is sent; waiting on `Set_client_DH_params_answer`
+    DHGen {
+        nonce: Int128,
+        server_nonce: Int128,
+        new_nonce: Int256,
+        dh: DiffieHellman,
+        auth_key: AuthKey,
+        time_offset: i32,
+    },
+
+    Done,
+}
+
+/// Parameters of the key exchange kept to answer `dh_gen_retry`
+struct DiffieHellman {
+    g: BigUint,
+    dh_prime: BigUint,
+    g_a: BigUint,
+    key: [u8; 32],
+    iv: [u8; 32],
+}
+
+/// Creation of an authorization key; independent of the transport used to exchange messages
+/// with the server.
+///
+/// `start` gives the first message to send; each answer of the server is passed to `handle`
+/// which either gives the next message to send or the created authorization key.
+// https://core.telegram.org/mtproto/auth_key
+pub struct Handshake<C = SystemClock> {
+    keys: Vec<RsaPublicKey>,
+    message_id: MessageIdGenerator<C>,
+    state: State,
+}
+
+impl Handshake<SystemClock> {
+    /// Create a handshake that encrypts with the first of the keys the server has a fingerprint of
+    pub fn new(keys: Vec<RsaPublicKey>) -> Handshake<SystemClock> {
+        Handshake::with_message_id(keys, MessageIdGenerator::new())
+    }
+}
+
+impl<C: Clock> Handshake<C> {
+    pub fn with_message_id(keys: Vec<RsaPublicKey>,
+                           message_id: MessageIdGenerator<C>)
+                           -> Handshake<C> {
+        Handshake {
+            keys: keys,
+            message_id: message_id,
+            state: State::Done,
+        }
+    }
+
+    /// Begin (or restart) the handshake; the message to send is `req_pq`
+    pub fn start(&mut self) -> Result<Vec<u8>> {
+        let nonce = Int128(random());
+        self.state = State::ResPQ { nonce: nonce };
+
+        self.message(ReqPq { nonce: nonce })
+    }
+
+    /// Handle an answer of the server
+    pub fn handle(&mut self, answer: &[u8]) -> Result<Step> {
+        match ::std::mem::replace(&mut self.state, State::Done) {
+            State::ResPQ { nonce } => self.handle_res_pq(answer, nonce),
+
+            State::ServerDHParams { nonce, server_nonce, new_nonce } => {
+                self.handle_server_dh_params(answer, nonce, server_nonce, new_nonce)
+            }
+
+            State::DHGen { nonce, server_nonce, new_nonce, dh, auth_key, time_offset } => {
+                self.handle_dh_gen(answer, nonce, server_nonce, new_nonce, dh, auth_key, time_offset)
+            }
+
+            State::Done => Err(ErrorKind::AuthKeyGeneration("no handshake in progress").into()),
+        }
+    }
+
+    fn handle_res_pq(&mut self, answer: &[u8], nonce: Int128) -> Result<Step> {
+        let res_pq = Message::<ResPQ>::from_slice(answer)?.body;
+        if res_pq.nonce != nonce {
+            return Err(ErrorKind::AuthKeyGeneration("nonce mismatch").into());
+        }
+
+        let key = match self.keys
+            .iter()
+            .find(|key| res_pq.server_public_key_fingerprints.contains(&key.fingerprint())) {
+            Some(key) => key.clone(),
+            None => return Err(ErrorKind::AuthKeyGeneration("no known server public key").into()),
+        };
+
+        // Proof of work; decompose pq into its two prime factors p < q
+        if res_pq.pq.is_empty() || res_pq.pq.len() > 8 {
+            return Err(ErrorKind::AuthKeyGeneration("pq does not fit in 64 bits").into());
+        }
+
+        let pq = BigEndian::read_uint(&res_pq.pq, res_pq.pq.len());
+        let (p, q) = match factorize(pq) {
+            Some(factors) => factors,
+            None => return Err(ErrorKind::AuthKeyGeneration("pq could not be factorized").into()),
+        };
+
+        let new_nonce = Int256(random());
+        let inner_data = P_Q_inner_data::p_q_inner_data {
+            pq: res_pq.pq,
+            p: ByteBuf::from(to_bytes_be(p)),
+            q: ByteBuf::from(to_bytes_be(q)),
+            nonce: nonce,
+            server_nonce: res_pq.server_nonce,
+            new_nonce: new_nonce,
+        };
+
+        let encrypted_data = key.encrypt(&Object::encode(&inner_data)?.0)?;
+
+        self.state = State::ServerDHParams {
+            nonce: nonce,
+            server_nonce: res_pq.server_nonce,
+            new_nonce: new_nonce,
+        };
+
+        self.message(ReqDHParams {
+                nonce: nonce,
+                server_nonce: res_pq.server_nonce,
+                p: ByteBuf::from(to_bytes_be(p)),
+                q: ByteBuf::from(to_bytes_be(q)),
+                public_key_fingerprint: key.fingerprint(),
+                encrypted_data: ByteBuf::from(encrypted_data),
+            })
+            .map(Step::Send)
+    }
+
+    fn handle_server_dh_params(&mut self,
+                               answer: &[u8],
+                               nonce: Int128,
+                               server_nonce: Int128,
+                               new_nonce: Int256)
+                               -> Result<Step> {
+        let encrypted_answer = match Message::<Server_DH_Params>::from_slice(answer)?.body {
+            Server_DH_Params::server_DH_params_ok { nonce: n, server_nonce: s, encrypted_answer } => {
+                check_nonces(n, s, nonce, server_nonce)?;
+                encrypted_answer
+            }
+
+            Server_DH_Params::server_DH_params_fail { nonce: n, server_nonce: s, new_nonce_hash } => {
+                check_nonces(n, s, nonce, server_nonce)?;
+
+                if new_nonce_hash.0 != sha1(&[&new_nonce.0])[4..] {
+                    return Err(ErrorKind::AuthKeyGeneration("new_nonce_hash mismatch").into());
+                }
+
+                return Err(ErrorKind::AuthKeyGeneration("server_DH_params_fail").into());
+            }
+        };
+
+        // tmp_aes_key := SHA1(new_nonce + server_nonce) + substr(SHA1(server_nonce + new_nonce), 0, 12)
+        // tmp_aes_iv := substr(SHA1(server_nonce + new_nonce), 12, 8) + SHA1(new_nonce + new_nonce)
+        //   + substr(new_nonce, 0, 4)
+        let new_nonce_server_nonce = sha1(&[&new_nonce.0, &server_nonce.0]);
+        let server_nonce_new_nonce = sha1(&[&server_nonce.0, &new_nonce.0]);
+        let new_nonce_new_nonce = sha1(&[&new_nonce.0, &new_nonce.0]);
+
+        let mut key = [0; 32];
+        key[..20].copy_from_slice(&new_nonce_server_nonce);
+        key[20..].copy_from_slice(&server_nonce_new_nonce[..12]);
+
+        let mut iv = [0; 32];
+        iv[..8].copy_from_slice(&server_nonce_new_nonce[12..]);
+        iv[8..28].copy_from_slice(&new_nonce_new_nonce);
+        iv[28..].copy_from_slice(&new_nonce.0[..4]);
+
+        if encrypted_answer.len() < 20 {
+            return Err(ErrorKind::AuthKeyGeneration("invalid encrypted_answer length").into());
+        }
+
+        // answer_with_hash := SHA1(answer) + answer + (0-15 random bytes)
+        let answer_with_hash = AesIge::new(&key, &iv).decrypt(&encrypted_answer)?;
+
+        let mut reader = &answer_with_hash[20..];
+        let inner_data = Server_DH_inner_data::deserialize(&mut Deserializer::new(&mut reader))?;
+
+        let len = answer_with_hash.len() - 20 - reader.len();
+        if reader.len() >= 16 || sha1(&[&answer_with_hash[20..20 + len]]) != answer_with_hash[..20] {
+            return Err(ErrorKind::AuthKeyGeneration("encrypted_answer hash mismatch").into());
+        }
+
+        check_nonces(inner_data.nonce, inner_data.server_nonce, nonce, server_nonce)?;
+
+        let g = BigUint::from(inner_data.g as u32);
+        let dh_prime = BigUint::from_bytes_be(&inner_data.dh_prime);
+        let g_a = BigUint::from_bytes_be(&inner_data.g_a);
+
+        check_dh_params(inner_data.g, &dh_prime)?;
+        check_dh_value(&g_a, &dh_prime)?;
+
+        self.message_id.update_time_offset((inner_data.server_time as i64) << 32);
+        let time_offset = self.message_id.time_offset();
+
+        let dh = DiffieHellman {
+            g: g,
+            dh_prime: dh_prime,
+            g_a: g_a,
+            key: key,
+            iv: iv,
+        };
+
+        self.set_client_dh_params(nonce, server_nonce, new_nonce, dh, 0, time_offset)
+    }
+
+    /// Generate `b` and send `g_b` to the server; `retry_id` is 0 on the first attempt or
+    /// `auth_key_aux_hash` of the previous attempt
+    fn set_client_dh_params(&mut self,
+                            nonce: Int128,
+                            server_nonce: Int128,
+                            new_nonce: Int256,
+                            dh: DiffieHellman,
+                            retry_id: i64,
+                            time_offset: i32)
+                            -> Result<Step> {
+        let mut b = vec![0; 256];
+        rand::thread_rng().fill_bytes(&mut b);
+
+        let b = BigUint::from_bytes_be(&b);
+        let g_b = dh.g.modpow(&b, &dh.dh_prime);
+        check_dh_value(&g_b, &dh.dh_prime)?;
+
+        let mut key = [0; 256];
+        let auth_key = dh.g_a.modpow(&b, &dh.dh_prime).to_bytes_be();
+        key[256 - auth_key.len()..].copy_from_slice(&auth_key);
+
+        let inner_data = Object::encode(&Client_DH_Inner_Data {
+                nonce: nonce,
+                server_nonce: server_nonce,
+                retry_id: retry_id,
+                g_b: ByteBuf::from(g_b.to_bytes_be()),
+            })?
+            .0;
+
+        // data_with_hash := SHA1(data) + data + (0-15 random bytes); a multiple of 16 bytes
+        let mut data_with_hash = sha1(&[&inner_data]).to_vec();
+        data_with_hash.extend(inner_data);
+
+        let mut padding = vec![0; (16 - data_with_hash.len() % 16) % 16];
+        rand::thread_rng().fill_bytes(&mut padding);
+        data_with_hash.extend(padding);
+
+        AesIge::new(&dh.key, &dh.iv).encrypt_in_place(&mut data_with_hash)?;
+
+        self.state = State::DHGen {
+            nonce: nonce,
+            server_nonce: server_nonce,
+            new_nonce: new_nonce,
+            dh: dh,
+            auth_key: AuthKey::new(key),
+            time_offset: time_offset,
+        };
+
+        self.message(SetClientDHParams {
+                nonce: nonce,
+                server_nonce: server_nonce,
+                encrypted_data: ByteBuf::from(data_with_hash),
+            })
+            .map(Step::Send)
+    }
+
+    fn handle_dh_gen(&mut self,
+                     answer: &[u8],
+                     nonce: Int128,
+                     server_nonce: Int128,
+                     new_nonce: Int256,
+                     dh: DiffieHellman,
+                     auth_key: AuthKey,
+                     time_offset: i32)
+                     -> Result<Step> {
+        // new_nonce_hash{1,2,3} := the lower 128 bits of SHA1(new_nonce + {1,2,3} + auth_key_aux_hash)
+        let new_nonce_hash = |number: u8| {
+            let mut aux_hash = [0; 8];
+            LittleEndian::write_i64(&mut aux_hash, auth_key.aux_hash);
+
+            let hash = sha1(&[&new_nonce.0, &[number], &aux_hash]);
+
+            let mut result = [0; 16];
+            result.copy_from_slice(&hash[4..]);
+            Int128(result)
+        };
+
+        match Message::<Set_client_DH_params_answer>::from_slice(answer)?.body {
+            Set_client_DH_params_answer::dh_gen_ok { nonce: n, server_nonce: s, new_nonce_hash1 } => {
+                check_nonces(n, s, nonce, server_nonce)?;
+
+                if new_nonce_hash1 != new_nonce_hash(1) {
+                    return Err(ErrorKind::AuthKeyGeneration("new_nonce_hash1 mismatch").into());
+                }
+
+                // server_salt := substr(new_nonce, 0, 8) XOR substr(server_nonce, 0, 8)
+                let server_salt = LittleEndian::read_i64(&new_nonce.0[..8]) ^
+                                  LittleEndian::read_i64(&server_nonce.0[..8]);
+
+                Ok(Step::Done(Authorization {
+                    auth_key: auth_key,
+                    server_salt: server_salt,
+                    time_offset: time_offset,
+                }))
+            }
+
+            Set_client_DH_params_answer::dh_gen_retry { nonce: n, server_nonce: s, new_nonce_hash2 } => {
+                check_nonces(n, s, nonce, server_nonce)?;
+
+                if new_nonce_hash2 != new_nonce_hash(2) {
+                    return Err(ErrorKind::AuthKeyGeneration("new_nonce_hash2 mismatch").into());
+                }
+
+                self.set_client_dh_params(nonce,
+                                          server_nonce,
+                                          new_nonce,
+                                          dh,
+                                          auth_key.aux_hash,
+                                          time_offset)
+            }
+
+            Set_client_DH_params_answer::dh_gen_fail { nonce: n, server_nonce: s, new_nonce_hash3 } => {
+                check_nonces(n, s, nonce, server_nonce)?;
+
+                if new_nonce_hash3 != new_nonce_hash(3) {
+                    return Err(ErrorKind::AuthKeyGeneration("new_nonce_hash3 mismatch").into());
+                }
+
+                Err(ErrorKind::AuthKeyGeneration("dh_gen_fail").into())
+            }
+        }
+    }
+
+    /// Wrap a request in an unencrypted message
+    fn message<T: Serialize>(&mut self, body: T) -> Result<Vec<u8>> {
+        Message::new(self.message_id.next_id(), body).to_vec()
+    }
+}
+
+/// Create an authorization key by exchanging messages through `send`; a function that
+/// sends a message to the server and returns its answer
+pub fn create_auth_key<F>(keys: Vec<RsaPublicKey>, mut send: F) -> Result<Authorization>
+    where F: FnMut(&[u8]) -> Result<Vec<u8>>
+{
+    let mut handshake = Handshake::new(keys);
+    let mut message = handshake.start()?;
+
+    loop {
+        let answer = send(&message)?;
+
+        match handshake.handle(&answer)? {
+            Step::Send(next) => message = next,
+            Step::Done(authorization) => return Ok(authorization),
+        }
+    }
+}
+
+fn check_nonces(nonce: Int128,
+                server_nonce: Int128,
+                expected_nonce: Int128,
+                expected_server_nonce: Int128)
+                -> Result<()> {
+    if nonce != expected_nonce {
+        return Err(ErrorKind::AuthKeyGeneration("nonce mismatch").into());
+    }
+
+    if server_nonce != expected_server_nonce {
+        return Err(ErrorKind::AuthKeyGeneration("server_nonce mismatch").into());
+    }
+
+    Ok(())
+}
+
+/// Check that `dh_prime` is a safe 2048-bit prime (both `dh_prime` and `(dh_prime - 1) / 2`
+/// are prime) and that `g` generates the cyclic subgroup of prime order `(dh_prime - 1) / 2`
+fn check_dh_params(g: i32, dh_prime: &BigUint) -> Result<()> {
+    if dh_prime.bits() != DH_PRIME_BITS {
+        return Err(ErrorKind::AuthKeyGeneration("dh_prime is not 2048 bits").into());
+    }
+
+    let known = BigUint::parse_bytes(KNOWN_DH_PRIME.as_bytes(), 16);
+    if known.as_ref() != Some(dh_prime) &&
+       !(is_probable_prime(dh_prime) && is_probable_prime(&((dh_prime - BigUint::from(1u32)) >> 1))) {
+        return Err(ErrorKind::AuthKeyGeneration("dh_prime is not a safe prime").into());
+    }
+
+    // The quadratic residues mod dh_prime form the subgroup; by quadratic reciprocity
+    // `g` is one iff dh_prime is in the given classes
+    let residue = |modulus: u32, residues: &[u32]| {
+        let rem = dh_prime % BigUint::from(modulus);
+        residues.iter().any(|residue| rem == BigUint::from(*residue))
+    };
+
+    let generates = match g {
+        2 => residue(8, &[7]),
+        3 => residue(3, &[2]),
+        4 => true,
+        5 => residue(5, &[1, 4]),
+        6 => residue(24, &[19, 23]),
+        7 => residue(7, &[3, 5, 6]),
+        _ => false,
+    };
+
+    if !generates {
+        return Err(ErrorKind::AuthKeyGeneration("g does not generate the subgroup of order \
+                                                 (dh_prime - 1) / 2")
+            .into());
+    }
+
+    Ok(())
+}
+
+/// Miller-Rabin test with random bases; `n` is greater than 3
+fn is_probable_prime(n: &BigUint) -> bool {
+    let one = BigUint::from(1u32);
+    let two = BigUint::from(2u32);
+    if (n % &two).bits() == 0 {
+        return false;
+    }
+
+    let n_minus_one = n - &one;
+
+    // n - 1 := d * 2^s with d odd
+    let mut d = n_minus_one.clone();
+    let mut s = 0;
+    while (&d % &two).bits() == 0 {
+        d = d >> 1;
+        s += 1;
+    }
+
+    let len = (n.bits() + 7) / 8;
+    'witness: for _ in 0..MILLER_RABIN_ROUNDS {
+        // a in [2, n - 2]
+        let mut bytes = vec![0; len];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let a = BigUint::from_bytes_be(&bytes) % (n - BigUint::from(3u32)) + &two;
+
+        let mut x = a.modpow(&d, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+
+        return false;
+    }
+
+    true
+}
+
+/// Check that `1 < value < dh_prime - 1` and, as recommended,
+/// that `2^(2048-64) <= value <= dh_prime - 2^(2048-64)`
+fn check_dh_value(value: &BigUint, dh_prime: &BigUint) -> Result<()> {
+    let min = BigUint::from(1u32) << (DH_PRIME_BITS - 64);
+    if *value < min || *value > dh_prime - &min {
+        return Err(ErrorKind::AuthKeyGeneration("DH value out of range").into());
+    }
+
+    Ok(())
+}
+
+/// Decompose a product of two primes with Pollard's rho algorithm; the smaller factor is first
+fn factorize(pq: u64) -> Option<(u64, u64)> {
+    if pq < 4 {
+        return None;
+    }
+
+    if pq % 2 == 0 {
+        return Some((2, pq / 2));
+    }
+
+    for c in 1..64u64 {
+        // x => x^2 + c (mod pq)
+        let f = |x: u64| ((x as u128 * x as u128 + c as u128) % pq as u128) as u64;
+
+        let (mut x, mut y, mut d) = (2, 2, 1);
+        while d == 1 {
+            x = f(x);
+            y = f(f(y));
+            d = gcd(if x > y { x - y } else { y - x }, pq);
+        }
+
+        if d != pq {
+            let (p, q) = (d, pq / d);
+            return Some(if p < q { (p, q) } else { (q, p) });
+        }
+    }
+
+    None
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+
+    a
+}
+
+fn random<T: Default + AsMut<[u8]>>() -> T {
+    let mut value = T::default();
+    rand::thread_rng().fill_bytes(value.as_mut());
+    value
+}
+
+/// Big-endian bytes without leading zeros (p and q are sent as `bytes`)
+fn to_bytes_be(value: u64) -> Vec<u8> {
+    let mut bytes = [0; 8];
+    BigEndian::write_u64(&mut bytes, value);
+
+    bytes.iter().cloned().skip_while(|byte| *byte == 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use num_bigint::BigUint;
+    use serde::bytes::ByteBuf;
+    use de::Deserializer;
+    use errors::*;
+    use tl::{Int128, Object};
+    use mtproto::ige::AesIge;
+    use mtproto::plain::Message;
+    use mtproto::rsa::RsaPublicKey;
+    use mtproto::schema::{ReqPq, ResPQ, ReqDHParams, P_Q_inner_data, Server_DH_Params,
+                          Server_DH_inner_data, SetClientDHParams, Client_DH_Inner_Data,
+                          Set_client_DH_params_answer};
+    use mtproto::sha1;
+    use serde::Deserialize;
+    use super::{AuthKey, Authorization, Handshake, Step, KNOWN_DH_PRIME, random};
+
+    // A 2048-bit RSA key of the fake server (e = 65537)
+    const N: &'static str = "9796f0df43a93e082505fab3fa2097d52659f38c51f84b811bf8c8e683719b9e\
+                            6ee89be5a5e47747387c48ee6f0b87d21940c56e27a6830a7965de11d33c1bb0\
+                            d3d3248853ed48168420b11d84a1616185233d7fb294ccaf5d373e1af60ab88b\
+                            4aeef66758c9f1e52d5719aafa2d612dd01df3f22f82949118efe7e58eaeeec6\
+                            ee6e87ca210098f28f8e31cb6550284089054e85281995eba7d577d854f16f61\
+                            b448667c76ce1e3fe546ae3fe18745be43426d23046450b415ce50944f18b57b\
+                            282c3a06b182bb2a696456bf312e54609064b738025fccf073c593b4d102f0fd\
+                            0e54a66b67c7261374391cc41b6567e6f7e230354562f9d55a7c6ad81ce08c25";
+    const D: &'static str = "11a6e67aa126e28dd13dde8139b5b4e881b444cb88ba0894cabc902c638f05a7\
+                            8fd3af5fb10f2722987dc4825b54f4e66ec15f9c21aadea4881968201c406a1b\
+                            0fb78d1000f1e70c942b3864af0e99c7e0d8872f490cd1bef3f45c5b885e1c3a\
+                            402a0e705f62f8b38eb18f501d1cceada687ee61bbcd915e4a1099a8de1219c4\
+                            cb9adfff2262c2afd40064799a85a0c234e03338afaa4bcd07fc79e53eea53fa\
+                            c823dff4ce1258bcf2d41f5827ee7b869e6cefc8074d7b34bd15e57bcb17bc88\
+                            962db206c0f2dbde8f89b5b826eb6e15ce2d4aaa0471cbc4a34d51c0c95b6721\
+                            f0cd0252cfa3eba0cc95157c10e7c0636d50be87ea36378d9e873d1d6a228751";
+
+    // pq = p * q as in the example of https://core.telegram.org/mtproto/samples-auth_key
+    const PQ: [u8; 8] = [0x17, 0xed, 0x48, 0x94, 0x1a, 0x08, 0xf9, 0x81];
+    const P: [u8; 4] = [0x49, 0x4c, 0x55, 0x3b];
+    const Q: [u8; 4] = [0x53, 0x91, 0x10, 0x73];
+
+    fn reply<T: ::serde::Serialize>(body: T) -> Vec<u8> {
+        Message::new(1 << 32 | 1, body).to_vec().unwrap()
+    }
+
+    fn sent<T: Deserialize>(step: Step) -> T {
+        match step {
+            Step::Send(message) => Message::<T>::from_slice(&message).unwrap().body,
+            Step::Done(_) => panic!("the handshake is done"),
+        }
+    }
+
+    /// Read the data of `SHA1(data) + data + padding` and check the hash
+    fn with_hash<T: Deserialize>(data_with_hash: &[u8]) -> T {
+        let mut reader = &data_with_hash[20..];
+        let value = T::deserialize(&mut Deserializer::new(&mut reader)).unwrap();
+
+        let len = data_with_hash.len() - 20 - reader.len();
+        assert_eq!(sha1(&[&data_with_hash[20..20 + len]]), data_with_hash[..20]);
+
+        value
+    }
+
+    fn new_nonce_hash(new_nonce: &[u8], number: u8, auth_key: &AuthKey) -> Int128 {
+        let mut aux_hash = [0; 8];
+        LittleEndian::write_i64(&mut aux_hash, auth_key.aux_hash);
+
+        let mut hash = [0; 16];
+        hash.copy_from_slice(&sha1(&[new_nonce, &[number], &aux_hash])[4..]);
+        Int128(hash)
+    }
+
+    /// Create an authorization key with a fake server that offers `g` and `dh_prime`; the server
+    /// asks the client to retry (`dh_gen_retry`) `retries` times. The key of the server is
+    /// returned along with the result of the client.
+    fn handshake(g: i32, dh_prime: BigUint, retries: usize) -> Result<(Authorization, AuthKey)> {
+        let n = BigUint::parse_bytes(N.as_bytes(), 16).unwrap();
+        let d = BigUint::parse_bytes(D.as_bytes(), 16).unwrap();
+        let key = RsaPublicKey::new(&n.to_bytes_be(), &[1, 0, 1]).unwrap();
+
+        let mut client = Handshake::new(vec![key.clone()]);
+
+        // req_pq => resPQ
+        let req_pq = Message::<ReqPq>::from_slice(&client.start()?).unwrap().body;
+        let server_nonce = Int128(random());
+
+        let step = client.handle(&reply(ResPQ {
+                nonce: req_pq.nonce,
+                server_nonce: server_nonce,
+                pq: ByteBuf::from(PQ.to_vec()),
+                server_public_key_fingerprints: vec![0, key.fingerprint()],
+            }))?;
+
+        // req_DH_params => server_DH_params_ok
+        let req_dh_params: ReqDHParams = sent(step);
+        assert_eq!(&*req_dh_params.p, &P);
+        assert_eq!(&*req_dh_params.q, &Q);
+        assert_eq!(req_dh_params.public_key_fingerprint, key.fingerprint());
+
+        let decrypted = BigUint::from_bytes_be(&req_dh_params.encrypted_data).modpow(&d, &n).to_bytes_be();
+        let mut data_with_hash = vec![0; 255 - decrypted.len()];
+        data_with_hash.extend(decrypted);
+
+        let new_nonce = match with_hash(&data_with_hash) {
+            P_Q_inner_data::p_q_inner_data { pq, p, q, nonce, server_nonce: s, new_nonce } => {
+                assert_eq!((&*pq, &*p, &*q), (&PQ[..], &P[..], &Q[..]));
+                assert_eq!((nonce, s), (req_pq.nonce, server_nonce));
+                new_nonce
+            }
+
+            _ => panic!("unexpected inner data"),
+        };
+
+        let new_nonce_server_nonce = sha1(&[&new_nonce.0, &server_nonce.0]);
+        let server_nonce_new_nonce = sha1(&[&server_nonce.0, &new_nonce.0]);
+        let new_nonce_new_nonce = sha1(&[&new_nonce.0, &new_nonce.0]);
+
+        let mut tmp_aes_key = [0; 32];
+        tmp_aes_key[..20].copy_from_slice(&new_nonce_server_nonce);
+        tmp_aes_key[20..].copy_from_slice(&server_nonce_new_nonce[..12]);
+
+        let mut tmp_aes_iv = [0; 32];
+        tmp_aes_iv[..8].copy_from_slice(&server_nonce_new_nonce[12..]);
+        tmp_aes_iv[8..28].copy_from_slice(&new_nonce_new_nonce);
+        tmp_aes_iv[28..].copy_from_slice(&new_nonce.0[..4]);
+
+        let tmp_aes = AesIge::new(&tmp_aes_key, &tmp_aes_iv);
+
+        let a = BigUint::from_bytes_be(&random::<[u8; 32]>());
+        let g_a = BigUint::from(g as u32).modpow(&a, &dh_prime);
+
+        let inner_data = Object::encode(&Server_DH_inner_data {
+                nonce: req_pq.nonce,
+                server_nonce: server_nonce,
+                g: g,
+                dh_prime: ByteBuf::from(dh_prime.to_bytes_be()),
+                g_a: ByteBuf::from(g_a.to_bytes_be()),
+                server_time: 1,
+            })
+            .unwrap()
+            .0;
+
+        let mut answer_with_hash = sha1(&[&inner_data]).to_vec();
+        answer_with_hash.extend(inner_data);
+        let padding = (16 - answer_with_hash.len() % 16) % 16;
+        answer_with_hash.extend(vec![0; padding]);
+
+        let mut step = client.handle(&reply(Server_DH_Params::server_DH_params_ok {
+                nonce: req_pq.nonce,
+                server_nonce: server_nonce,
+                encrypted_answer: ByteBuf::from(tmp_aes.encrypt(&answer_with_hash).unwrap()),
+            }))?;
+
+        // set_client_DH_params => dh_gen_retry, .., dh_gen_ok
+        let mut retry_id = 0;
+        for retry in 0..retries + 1 {
+            let set_client_dh_params: SetClientDHParams = sent(step);
+            let data_with_hash = tmp_aes.decrypt(&set_client_dh_params.encrypted_data).unwrap();
+            let inner_data: Client_DH_Inner_Data = with_hash(&data_with_hash);
+            assert_eq!(inner_data.retry_id, retry_id);
+
+            let auth_key = BigUint::from_bytes_be(&inner_data.g_b).modpow(&a, &dh_prime).to_bytes_be();
+            let mut key = [0; 256];
+            key[256 - auth_key.len()..].copy_from_slice(&auth_key);
+            let auth_key = AuthKey::new(key);
+
+            let answer = if retry < retries {
+                Set_client_DH_params_answer::dh_gen_retry {
+                    nonce: req_pq.nonce,
+                    server_nonce: server_nonce,
+                    new_nonce_hash2: new_nonce_hash(&new_nonce.0, 2, &auth_key),
+                }
+            } else {
+                Set_client_DH_params_answer::dh_gen_ok {
+                    nonce: req_pq.nonce,
+                    server_nonce: server_nonce,
+                    new_nonce_hash1: new_nonce_hash(&new_nonce.0, 1, &auth_key),
+                }
+            };
+
+            step = client.handle(&reply(answer))?;
+            retry_id = auth_key.aux_hash;
+
+            if let Step::Done(authorization) = step {
+                assert_eq!(retry, retries);
+                return Ok((authorization, auth_key));
+            }
+        }
+
+        panic!("the handshake is not done");
+    }
+
+    fn known_dh_prime() -> BigUint {
+        BigUint::parse_bytes(KNOWN_DH_PRIME.as_bytes(), 16).unwrap()
+    }
+
+    #[test]
+    fn create_auth_key() {
+        let (authorization, auth_key) = handshake(3, known_dh_prime(), 0).unwrap();
+
+        assert_eq!(&authorization.auth_key.key()[..], &auth_key.key()[..]);
+        assert_eq!(authorization.auth_key.id(), auth_key.id());
+    }
+
+    #[test]
+    fn create_auth_key_after_retry() {
+        let (authorization, auth_key) = handshake(3, known_dh_prime(), 1).unwrap();
+
+        assert_eq!(&authorization.auth_key.key()[..], &auth_key.key()[..]);
+    }
+
+    #[test]
+    fn reject_dh_prime_not_prime() {
+        // An odd 2048-bit composite (divisible by 3)
+        let dh_prime = known_dh_prime() - BigUint::from(2u32);
+
+        match handshake(3, dh_prime, 0) {
+            Err(Error(ErrorKind::AuthKeyGeneration(reason), _)) => {
+                assert_eq!(reason, "dh_prime is not a safe prime")
+            }
+
+            _ => panic!("dh_prime is accepted"),
+        }
+    }
+
+    #[test]
+    fn reject_g_outside_subgroup() {
+        // dh_prime = 3 (mod 8); 2 is not a quadratic residue
+        match handshake(2, known_dh_prime(), 0) {
+            Err(Error(ErrorKind::AuthKeyGeneration(reason), _)) => assert!(reason.starts_with("g does not")),
+            _ => panic!("g is accepted"),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use serde::Deserialize;
+use errors::*;
+use tl::Object;
+use super::encrypted::Message;
+use super::schema;
+
+/// Constructor ID of `msg_container#73f1f8dc messages:vector<%Message> = MessageContainer`
+pub const MSG_CONTAINER: u32 = 0x73f1f8dc;
+
+/// Most messages a container may hold
+pub const MAX_MESSAGES: usize = 1020;
+
+/// Split a received container (`msg_container`) into its messages and decode their bodies
+/// as `T` (`Object` to tell them apart by their constructor IDs later).
+///
+/// The body of each message is read as long as its `bytes`; decoding it must then read all
+/// of it.
+// https://core.telegram.org/mtproto/service_messages#containers
+pub fn unpack<T: Deserialize>(container: &Object) -> Result<Vec<Message<T>>> {
+    let container = container.decode::<schema::MessageContainer>()?;
+    if container.messages.len() > MAX_MESSAGES {
+        return Err(ErrorKind::InvalidContainerLength(container.messages.len()).into());
+    }
+
+    container.messages
+        .into_iter()
+        .map(|message| {
+            Ok(Message {
+                message_id: message.msg_id,
+                seq_no: message.seqno,
+                body: message.body.decode()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use errors::*;
+    use tl::Object;
+    use super::super::schema;
+    use super::{MSG_CONTAINER, unpack};
+
+    /// A container of the messages (msg_id, seqno, bytes, body) as they are given
+    fn container(messages: &[(i64, i32, i32, &[u8])]) -> Object {
+        let mut buffer = Vec::new();
+        buffer.write_u32::<LittleEndian>(MSG_CONTAINER).unwrap();
+        buffer.write_i32::<LittleEndian>(messages.len() as i32).unwrap();
+
+        for &(msg_id, seqno, bytes, body) in messages {
+            buffer.write_i64::<LittleEndian>(msg_id).unwrap();
+            buffer.write_i32::<LittleEndian>(seqno).unwrap();
+            buffer.write_i32::<LittleEndian>(bytes).unwrap();
+            buffer.extend_from_slice(body);
+        }
+
+        Object(buffer)
+    }
+
+    fn pong(ping_id: i64) -> Vec<u8> {
+        Object::encode(&schema::Pong {
+                msg_id: 1,
+                ping_id: ping_id,
+            })
+            .unwrap()
+            .0
+    }
+
+    fn assert_invalid_length(result: Result<Vec<::mtproto::encrypted::Message<schema::Pong>>>) {
+        match result {
+            Err(Error(ErrorKind::InvalidMessageLength(..), _)) => {}
+            _ => panic!("the container is unpacked"),
+        }
+    }
+
+    #[test]
+    fn unpack_messages() {
+        let first = pong(10);
+        let second = pong(20);
+        let container = container(&[(4, 1, first.len() as i32, &first),
+                                    (8, 3, second.len() as i32, &second)]);
+
+        let messages = unpack::<schema::Pong>(&container).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!((messages[0].message_id, messages[0].seq_no, messages[0].body.ping_id),
+                   (4, 1, 10));
+        assert_eq!((messages[1].message_id, messages[1].seq_no, messages[1].body.ping_id),
+                   (8, 3, 20));
+
+        // Bodies kept serialized are split at their lengths
+        let messages = unpack::<Object>(&container).unwrap();
+        assert_eq!(messages[0].body.0, first);
+        assert_eq!(messages[1].body.0, second);
+    }
+
+    #[test]
+    fn decode_packed_container() {
+        let messages = (0..3)
+            .map(|index| {
+                let body = Object(pong(index));
+                schema::Message {
+                    msg_id: index * 4,
+                    seqno: index as i32 * 2 + 1,
+                    bytes: body.0.len() as i32,
+                    body: body,
+                }
+            })
+            .collect();
+
+        let container = Object::encode(&schema::MessageContainer { messages: messages }).unwrap();
+        let container = container.decode::<schema::MessageContainer>().unwrap();
+
+        assert_eq!(container.messages.len(), 3);
+        for (index, message) in container.messages.iter().enumerate() {
+            assert_eq!((message.msg_id, message.seqno), (index as i64 * 4, index as i32 * 2 + 1));
+            assert_eq!(message.body.0, pong(index as i64));
+        }
+    }
+
+    #[test]
+    fn reject_body_shorter_than_bytes() {
+        // The length takes in 4 bytes the body does not read
+        let mut first = pong(10);
+        first.extend_from_slice(&[0; 4]);
+        let second = pong(20);
+
+        assert_invalid_length(unpack(&container(&[(4, 1, first.len() as i32, &first),
+                                                  (8, 3, second.len() as i32, &second)])));
+    }
+
+    #[test]
+    fn reject_body_longer_than_bytes() {
+        // The length leaves out the last 4 bytes of the body (`ping_id` is cut short)
+        let first = pong(10);
+        let second = pong(20);
+
+        match unpack::<schema::Pong>(&container(&[(4, 1, first.len() as i32 - 4, &first),
+                                                  (8, 3, second.len() as i32, &second)])) {
+            Err(_) => {}
+            Ok(_) => panic!("the container is unpacked"),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_bytes() {
+        let body = pong(10);
+
+        // Negative, not a multiple of 4, past the end of the container
+        for &bytes in &[-4, body.len() as i32 - 1, body.len() as i32 + 4] {
+            match unpack::<schema::Pong>(&container(&[(4, 1, bytes, &body)])) {
+                Err(_) => {}
+                Ok(_) => panic!("a message of {} bytes is unpacked", bytes),
+            }
+        }
+    }
+
+    #[test]
+    fn reject_trailing_bytes() {
+        let body = pong(10);
+        let mut container = container(&[(4, 1, body.len() as i32, &body)]);
+        container.0.extend_from_slice(&[0; 4]);
+
+        assert_invalid_length(unpack(&container));
+    }
+}
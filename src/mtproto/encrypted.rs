@@ -0,0 +1,942 @@
+use std::collections::HashMap;
+use std::io::Write;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2;
+use flate2::write::GzEncoder;
+use rand::{self, Rng};
+use serde::{Serialize, Deserialize};
+use serde::bytes::Bytes;
+use errors::*;
+use ser::Serializer;
+use de::{self, Deserializer};
+use tl::{self, Object};
+use super::auth::{AuthKey, Authorization};
+use super::container;
+use super::ige::AesIge;
+use super::msg_id::{Clock, MessageIdGenerator, SystemClock};
+use super::schema;
+use super::{sha1, sha256};
+
+/// Length of the envelope before the encrypted data
+///  - auth_key_id: i64
+///  - msg_key: int128
+const HEADER_LEN: usize = 8 + 16;
+
+/// Length of the plaintext before the message data
+///  - salt: i64
+///  - session_id: i64
+///  - message_id: i64
+///  - seq_no: i32
+///  - message_data_length: i32
+const PLAINTEXT_HEADER_LEN: usize = 8 + 8 + 8 + 4 + 4;
+
+/// Seconds a sent message is kept to be resent; the server rejects older messages anyway
+const RESEND_WINDOW: i64 = 300;
+
+/// Version of the encryption of messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// MTProto 1.0; msg_key and the AES key are derived with SHA1
+    // https://core.telegram.org/mtproto_v1
+    V1,
+
+    /// MTProto 2.0; msg_key and the AES key are derived with SHA256
+    V2,
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Version::V2
+    }
+}
+
+impl Version {
+    /// Bounds of the length of the padding after the message data
+    fn padding_len(self) -> (usize, usize) {
+        match self {
+            Version::V1 => (0, 15),
+            Version::V2 => (12, 1024),
+        }
+    }
+}
+
+/// Packing of the data of large content-related messages (calls) with `gzip_packed`
+/// before they are sent
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+    /// Data shorter than this (in bytes) is sent as is
+    pub threshold: usize,
+
+    pub level: flate2::Compression,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            threshold: 1024,
+            level: flate2::Compression::Default,
+        }
+    }
+}
+
+impl Compression {
+    /// Pack the data if it is large enough and packing saves at least an eighth of it
+    fn pack(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        if data.len() < self.threshold {
+            return Ok(data);
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), self.level);
+        encoder.write_all(&data)?;
+        let packed_data = encoder.finish()?;
+
+        let mut packed = Vec::with_capacity(packed_data.len() + 8);
+        packed.write_u32::<LittleEndian>(tl::GZIP_PACKED)?;
+        Bytes::new(&packed_data).serialize(&mut Serializer::new(&mut packed))?;
+
+        if packed.len() > data.len() - data.len() / 8 {
+            debug!("not packing {} bytes; gzip_packed would be {} bytes",
+                   data.len(),
+                   packed.len());
+
+            return Ok(data);
+        }
+
+        debug!("packed {} bytes with gzip_packed to {} bytes", data.len(), packed.len());
+
+        Ok(packed)
+    }
+}
+
+/// A message received in an encrypted session
+#[derive(Debug)]
+pub struct Message<T> {
+    pub message_id: i64,
+    pub seq_no: i32,
+    pub body: T,
+}
+
+/// A message encrypted again to be resent (`EncryptedSession::resend`)
+#[derive(Debug)]
+pub struct Resent {
+    /// New identifier of the message
+    pub message_id: i64,
+
+    pub message: Vec<u8>,
+
+    /// New identifiers of the messages in a resent container by their old ones
+    pub renumbered: Vec<(i64, i64)>,
+}
+
+/// Sender of a message; the part of the authorization key used to encrypt it depends on it
+#[derive(Clone, Copy)]
+enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    /// Offset into the authorization key (`x`)
+    fn offset(self) -> usize {
+        match self {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 8,
+        }
+    }
+}
+
+/// A session with the server encrypted with an authorization key
+// https://core.telegram.org/mtproto/description
+pub struct EncryptedSession<C = SystemClock> {
+    version: Version,
+    auth_key: AuthKey,
+    session_id: i64,
+    salt: i64,
+    message_id: MessageIdGenerator<C>,
+
+    /// Number of content-related messages sent in the session
+    content_related_count: i32,
+
+    /// Most bytes a `gzip_packed` object in a received message may inflate to
+    max_inflated_len: usize,
+
+    /// Packing of sent content-related messages; none if not set
+    compression: Option<Compression>,
+
+    /// Data of the messages sent recently (and whether they are content-related) by
+    /// their identifier; to resend them
+    sent: HashMap<i64, (Vec<u8>, bool)>,
+}
+
+impl EncryptedSession<SystemClock> {
+    /// Create a new session (with a random identifier) from a created authorization key
+    pub fn new(authorization: Authorization) -> EncryptedSession<SystemClock> {
+        let mut message_id = MessageIdGenerator::new();
+        message_id.set_time_offset(authorization.time_offset);
+
+        EncryptedSession::with_message_id(authorization.auth_key,
+                                          authorization.server_salt,
+                                          message_id)
+    }
+}
+
+impl<C: Clock> EncryptedSession<C> {
+    pub fn with_message_id(auth_key: AuthKey,
+                           salt: i64,
+                           message_id: MessageIdGenerator<C>)
+                           -> EncryptedSession<C> {
+        EncryptedSession {
+            version: Version::default(),
+            auth_key: auth_key,
+            session_id: rand::thread_rng().gen(),
+            salt: salt,
+            message_id: message_id,
+            content_related_count: 0,
+            max_inflated_len: de::DEFAULT_MAX_INFLATED_LEN,
+            compression: None,
+            sent: HashMap::new(),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub fn session_id(&self) -> i64 {
+        self.session_id
+    }
+
+    pub fn salt(&self) -> i64 {
+        self.salt
+    }
+
+    pub fn set_salt(&mut self, salt: i64) {
+        self.salt = salt;
+    }
+
+    pub fn max_inflated_len(&self) -> usize {
+        self.max_inflated_len
+    }
+
+    pub fn set_max_inflated_len(&mut self, len: usize) {
+        self.max_inflated_len = len;
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// Pack the data of large content-related messages sent from now on with `gzip_packed`
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    pub fn message_id(&mut self) -> &mut MessageIdGenerator<C> {
+        &mut self.message_id
+    }
+
+    /// Encrypt a message to send to the server; the identifier of the message is returned
+    /// along with it.
+    ///
+    /// A message that requires an acknowledgment (a call or a container of calls)
+    /// is content-related.
+    pub fn seal<T: Serialize>(&mut self, body: &T, content_related: bool) -> Result<(i64, Vec<u8>)> {
+        let data = self.serialize(body, content_related)?;
+
+        let message_id = self.message_id.next_id();
+        let seq_no = self.next_seq_no(content_related);
+        let message = self.encrypt(message_id, seq_no, &data)?;
+
+        self.record(message_id, data, content_related);
+
+        Ok((message_id, message))
+    }
+
+    /// Encrypt a message sent recently again (with the current salt) under a new identifier.
+    ///
+    /// The messages in a resent container (`msg_container`) are numbered again as well; the
+    /// server rejects identifiers it has already seen. There is nothing to resend if the
+    /// message is unknown or was sent too long ago.
+    pub fn resend(&mut self, message_id: i64) -> Result<Option<Resent>> {
+        let (data, content_related) = match self.sent.remove(&message_id) {
+            Some(sent) => sent,
+            None => return Ok(None),
+        };
+
+        let mut renumbered = Vec::new();
+        let data = Object(data);
+        let data = if data.constructor_id() == Some(container::MSG_CONTAINER) {
+            let mut container = data.decode::<schema::MessageContainer>()?;
+            for message in &mut container.messages {
+                // NOTE: Content-related messages have an odd seq_no
+                let new_msg_id = self.message_id.next_id();
+                let seqno = self.next_seq_no(message.seqno % 2 == 1);
+
+                debug!("renumbering message {} in a resent container as {}",
+                       message.msg_id,
+                       new_msg_id);
+                renumbered.push((message.msg_id, new_msg_id));
+
+                message.msg_id = new_msg_id;
+                message.seqno = seqno;
+            }
+
+            let mut data = Vec::new();
+            container.serialize(&mut Serializer::new(&mut data))?;
+            data
+        } else {
+            data.0
+        };
+
+        let new_message_id = self.message_id.next_id();
+        let seq_no = self.next_seq_no(content_related);
+        let message = self.encrypt(new_message_id, seq_no, &data)?;
+
+        debug!("resending message {} as {}", message_id, new_message_id);
+        self.record(new_message_id, data, content_related);
+
+        Ok(Some(Resent {
+            message_id: new_message_id,
+            message: message,
+            renumbered: renumbered,
+        }))
+    }
+
+    /// Keep the data of a sent message to resend it; messages sent too long ago are dropped
+    fn record(&mut self, message_id: i64, data: Vec<u8>, content_related: bool) {
+        let oldest = (message_id >> 32) - RESEND_WINDOW;
+        self.sent.retain(|id, _| id >> 32 >= oldest);
+
+        self.sent.insert(message_id, (data, content_related));
+    }
+
+    /// Number a message to send in a container (`msg_container`) instead of on its own
+    pub fn pack<T: Serialize>(&mut self, body: &T, content_related: bool) -> Result<schema::Message> {
+        let body = Object(self.serialize(body, content_related)?);
+
+        Ok(schema::Message {
+            msg_id: self.message_id.next_id(),
+            seqno: self.next_seq_no(content_related),
+            bytes: body.0.len() as i32,
+            body: body,
+        })
+    }
+
+    /// Encrypt a container of messages (numbered with `pack`) to send to the server; the
+    /// identifier of the container is returned along with it.
+    ///
+    /// The container itself is not content-related; the messages in it are acknowledged
+    /// on their own.
+    pub fn seal_container(&mut self, messages: Vec<schema::Message>) -> Result<(i64, Vec<u8>)> {
+        if messages.is_empty() || messages.len() > container::MAX_MESSAGES {
+            return Err(ErrorKind::InvalidContainerLength(messages.len()).into());
+        }
+
+        self.seal(&schema::MessageContainer { messages: messages }, false)
+    }
+
+    /// Serialize the body of a message to send; packed if it is content-related and
+    /// compression is set
+    fn serialize<T: Serialize>(&self, body: &T, content_related: bool) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        body.serialize(&mut Serializer::new(&mut data))?;
+
+        match self.compression {
+            Some(ref compression) if content_related => compression.pack(data),
+            _ => Ok(data),
+        }
+    }
+
+    /// Number the next message sent in the session
+    fn next_seq_no(&mut self, content_related: bool) -> i32 {
+        // seq_no := 2 * (content-related messages sent before) + 1 (if content-related)
+        if content_related {
+            self.content_related_count += 1;
+            self.content_related_count * 2 - 1
+        } else {
+            self.content_related_count * 2
+        }
+    }
+
+    /// Encrypt the serialized data of a numbered message
+    fn encrypt(&self, message_id: i64, seq_no: i32, data: &[u8]) -> Result<Vec<u8>> {
+        // Pad to a multiple of 16 bytes with random data
+        let (min_padding_len, _) = self.version.padding_len();
+        let len = PLAINTEXT_HEADER_LEN + data.len();
+        let padding_len = min_padding_len + (16 - (len + min_padding_len) % 16) % 16;
+
+        let mut plaintext = Vec::with_capacity(len + padding_len);
+        plaintext.write_i64::<LittleEndian>(self.salt)?;
+        plaintext.write_i64::<LittleEndian>(self.session_id)?;
+        plaintext.write_i64::<LittleEndian>(message_id)?;
+        plaintext.write_i32::<LittleEndian>(seq_no)?;
+        plaintext.write_i32::<LittleEndian>(data.len() as i32)?;
+        plaintext.write_all(data)?;
+
+        let mut padding = vec![0; padding_len];
+        rand::thread_rng().fill_bytes(&mut padding);
+        plaintext.write_all(&padding)?;
+
+        let msg_key = self.msg_key(&plaintext, data.len(), Direction::ClientToServer);
+        self.cipher(&msg_key, Direction::ClientToServer).encrypt_in_place(&mut plaintext)?;
+
+        let mut buffer = Vec::with_capacity(HEADER_LEN + plaintext.len());
+        buffer.write_i64::<LittleEndian>(self.auth_key.id())?;
+        buffer.write_all(&msg_key)?;
+        buffer.write_all(&plaintext)?;
+
+        Ok(buffer)
+    }
+
+    /// Decrypt a message received from the server
+    pub fn open<T: Deserialize>(&self, mut buffer: &[u8]) -> Result<Message<T>> {
+        let (min_padding_len, max_padding_len) = self.version.padding_len();
+        if buffer.len() < HEADER_LEN + PLAINTEXT_HEADER_LEN + min_padding_len {
+            return Err(ErrorKind::InvalidMessageLength(HEADER_LEN + PLAINTEXT_HEADER_LEN +
+                                                       min_padding_len,
+                                                       buffer.len())
+                .into());
+        }
+
+        let auth_key_id = buffer.read_i64::<LittleEndian>()?;
+        if auth_key_id != self.auth_key.id() {
+            return Err(ErrorKind::UnexpectedAuthKeyId(auth_key_id).into());
+        }
+
+        let mut msg_key = [0; 16];
+        msg_key.copy_from_slice(&buffer[..16]);
+
+        let mut plaintext = buffer[16..].to_vec();
+        self.cipher(&msg_key, Direction::ServerToClient).decrypt_in_place(&mut plaintext)?;
+
+        // NOTE: The length is needed to recompute msg_key (MTProto 1.0 leaves out the padding)
+        let len = LittleEndian::read_i32(&plaintext[PLAINTEXT_HEADER_LEN - 4..]);
+        let data_len = plaintext.len() - PLAINTEXT_HEADER_LEN;
+        let valid_len = len >= 0 && len % 4 == 0 && len as usize <= data_len;
+
+        // The message key must be recomputed from the decrypted data before trusting any of it;
+        // a garbled length does not match it either
+        let checked_len = if valid_len { len as usize } else { 0 };
+        if self.msg_key(&plaintext, checked_len, Direction::ServerToClient) != msg_key {
+            return Err(ErrorKind::InvalidMsgKey.into());
+        }
+
+        if !valid_len {
+            return Err(ErrorKind::InvalidMessageLength(len as usize, data_len).into());
+        }
+
+        let mut buffer = &plaintext[..];
+
+        // NOTE: The salt is checked by the server only
+        let _salt = buffer.read_i64::<LittleEndian>()?;
+
+        let session_id = buffer.read_i64::<LittleEndian>()?;
+        if session_id != self.session_id {
+            return Err(ErrorKind::UnexpectedSessionId(session_id).into());
+        }
+
+        let message_id = buffer.read_i64::<LittleEndian>()?;
+        let seq_no = buffer.read_i32::<LittleEndian>()?;
+        let _len = buffer.read_i32::<LittleEndian>()?;
+
+        let padding_len = buffer.len() - len as usize;
+        if padding_len < min_padding_len || padding_len > max_padding_len {
+            return Err(ErrorKind::InvalidPaddingLength(padding_len).into());
+        }
+
+        let mut data = &buffer[..len as usize];
+        let body = {
+            let mut de = Deserializer::new(&mut data);
+            de.set_max_inflated_len(self.max_inflated_len);
+
+            T::deserialize(&mut de)?
+        };
+        if !data.is_empty() {
+            return Err(ErrorKind::InvalidMessageLength(len as usize, len as usize - data.len())
+                .into());
+        }
+
+        Ok(Message {
+            message_id: message_id,
+            seq_no: seq_no,
+            body: body,
+        })
+    }
+
+    /// Compute msg_key of the plaintext; `len` is the length of the message data in it
+    fn msg_key(&self, plaintext: &[u8], len: usize, direction: Direction) -> [u8; 16] {
+        let mut msg_key = [0; 16];
+
+        match self.version {
+            Version::V1 => {
+                // msg_key := substr(SHA1(plaintext without the padding), 4, 16)
+                let hash = sha1(&[&plaintext[..PLAINTEXT_HEADER_LEN + len]]);
+                msg_key.copy_from_slice(&hash[4..]);
+            }
+
+            Version::V2 => {
+                // msg_key := substr(SHA256(substr(auth_key, 88 + x, 32) + plaintext), 8, 16)
+                let x = direction.offset();
+                let hash = sha256(&[&self.auth_key.key()[88 + x..120 + x], plaintext]);
+                msg_key.copy_from_slice(&hash[8..24]);
+            }
+        }
+
+        msg_key
+    }
+
+    /// AES cipher of a message with msg_key
+    fn cipher(&self, msg_key: &[u8; 16], direction: Direction) -> AesIge {
+        let (key, iv) = self.key_iv(msg_key, direction);
+        AesIge::new(&key, &iv)
+    }
+
+    /// Derive the AES key and initialization vector from msg_key
+    fn key_iv(&self, msg_key: &[u8; 16], direction: Direction) -> ([u8; 32], [u8; 32]) {
+        let x = direction.offset();
+        let auth_key = self.auth_key.key();
+
+        let mut key = [0; 32];
+        let mut iv = [0; 32];
+
+        match self.version {
+            Version::V1 => {
+                // sha1_a := SHA1(msg_key + substr(auth_key, x, 32))
+                // sha1_b := SHA1(substr(auth_key, 32 + x, 16) + msg_key + substr(auth_key, 48 + x, 16))
+                // sha1_c := SHA1(substr(auth_key, 64 + x, 32) + msg_key)
+                // sha1_d := SHA1(msg_key + substr(auth_key, 96 + x, 32))
+                let a = sha1(&[msg_key, &auth_key[x..32 + x]]);
+                let b = sha1(&[&auth_key[32 + x..48 + x], msg_key, &auth_key[48 + x..64 + x]]);
+                let c = sha1(&[&auth_key[64 + x..96 + x], msg_key]);
+                let d = sha1(&[msg_key, &auth_key[96 + x..128 + x]]);
+
+                // aes_key := substr(sha1_a, 0, 8) + substr(sha1_b, 8, 12) + substr(sha1_c, 4, 12)
+                key[..8].copy_from_slice(&a[..8]);
+                key[8..20].copy_from_slice(&b[8..]);
+                key[20..].copy_from_slice(&c[4..16]);
+
+                // aes_iv := substr(sha1_a, 8, 12) + substr(sha1_b, 0, 8) + substr(sha1_c, 16, 4)
+                //   + substr(sha1_d, 0, 8)
+                iv[..12].copy_from_slice(&a[8..]);
+                iv[12..20].copy_from_slice(&b[..8]);
+                iv[20..24].copy_from_slice(&c[16..]);
+                iv[24..].copy_from_slice(&d[..8]);
+            }
+
+            Version::V2 => {
+                // sha256_a := SHA256(msg_key + substr(auth_key, x, 36))
+                // sha256_b := SHA256(substr(auth_key, 40 + x, 36) + msg_key)
+                let a = sha256(&[msg_key, &auth_key[x..x + 36]]);
+                let b = sha256(&[&auth_key[40 + x..76 + x], msg_key]);
+
+                // aes_key := substr(sha256_a, 0, 8) + substr(sha256_b, 8, 16) + substr(sha256_a, 24, 8)
+                key[..8].copy_from_slice(&a[..8]);
+                key[8..24].copy_from_slice(&b[8..24]);
+                key[24..].copy_from_slice(&a[24..]);
+
+                // aes_iv := substr(sha256_b, 0, 8) + substr(sha256_a, 8, 16) + substr(sha256_b, 24, 8)
+                iv[..8].copy_from_slice(&b[..8]);
+                iv[8..24].copy_from_slice(&a[8..24]);
+                iv[24..].copy_from_slice(&b[24..]);
+            }
+        }
+
+        (key, iv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+    use rand;
+    use errors::*;
+    use tl::{self, Object};
+    use mtproto::auth::AuthKey;
+    use mtproto::container;
+    use mtproto::msg_id::MessageIdGenerator;
+    use mtproto::schema;
+    use super::{Compression, Direction, EncryptedSession, Version, HEADER_LEN, PLAINTEXT_HEADER_LEN};
+
+    // Known answers of the MTProto 2.0 key derivation (as computed by grammers) with the bytes
+    // 0, 1, .., 255 as the authorization key and 0, 1, .., 15 as msg_key
+    const V2_CLIENT_KEY: [u8; 32] = [0x70, 0x4e, 0xd0, 0x9c, 0x8b, 0x41, 0x66, 0x8a, 0xe8, 0xf9,
+                                     0x9d, 0x24, 0x47, 0x38, 0xf7, 0x1d, 0xbd, 0xdc, 0x44, 0x46,
+                                     0x9b, 0x6b, 0xbd, 0x4a, 0xa8, 0x57, 0x3d, 0xd0, 0x42, 0xbd,
+                                     0x05, 0x9e];
+
+    const V2_CLIENT_IV: [u8; 32] = [0x4d, 0x26, 0x60, 0x00, 0xa5, 0x50, 0xed, 0xab, 0xbf, 0x4c,
+                                    0x7c, 0xe4, 0x0f, 0xd0, 0x04, 0x3c, 0xc9, 0x22, 0x30, 0x18,
+                                    0x4c, 0xd3, 0x17, 0xa5, 0xcc, 0x9c, 0x24, 0x82, 0xfd, 0x3b,
+                                    0x93, 0x18];
+
+    const V2_SERVER_KEY: [u8; 32] = [0x21, 0x77, 0x25, 0x79, 0x9b, 0x24, 0x58, 0x06, 0x45, 0x81,
+                                     0x74, 0xa1, 0xfc, 0xfb, 0xc8, 0x83, 0x90, 0x68, 0x07, 0xb1,
+                                     0x50, 0x33, 0xfd, 0xd0, 0xea, 0x2b, 0x4d, 0x69, 0xcf, 0x9c,
+                                     0x36, 0x4e];
+
+    const V2_SERVER_IV: [u8; 32] = [0x66, 0x9a, 0x65, 0x38, 0x91, 0x7a, 0x4f, 0xa5, 0x6c, 0xa3,
+                                    0x23, 0x60, 0xa4, 0x31, 0xc9, 0x16, 0x0b, 0xe4, 0xad, 0x88,
+                                    0x71, 0x40, 0x98, 0x0d, 0xab, 0x91, 0xce, 0x7b, 0xdc, 0x47,
+                                    0xff, 0xbc];
+
+
+    // Known answers of the MTProto 1.0 key derivation with the same authorization key and
+    // msg_key (computed from the formulas of https://core.telegram.org/mtproto_v1)
+    const V1_CLIENT_KEY: [u8; 32] = [0x17, 0xd7, 0x29, 0x5c, 0xa9, 0x21, 0x3d, 0x1a, 0xb6, 0x56,
+                                     0xac, 0xdb, 0x1a, 0xd4, 0x8b, 0x2e, 0xa7, 0xf3, 0xa8, 0xf7,
+                                     0x09, 0x50, 0x98, 0xd5, 0x50, 0x8b, 0x90, 0x0b, 0xbd, 0x5f,
+                                     0xcc, 0xfc];
+
+    const V1_CLIENT_IV: [u8; 32] = [0x2d, 0x7d, 0x16, 0xa6, 0x5a, 0x84, 0x10, 0x8e, 0x98, 0x05,
+                                    0x65, 0x6c, 0xaa, 0x47, 0x45, 0x01, 0xcc, 0x58, 0x0a, 0xa2,
+                                    0xed, 0xc3, 0x3a, 0xbf, 0xd0, 0xbf, 0xad, 0x78, 0x54, 0x64,
+                                    0xd1, 0xc6];
+
+    const V1_SERVER_KEY: [u8; 32] = [0xbb, 0x17, 0xb0, 0x7e, 0xb9, 0x11, 0x10, 0x64, 0x70, 0x98,
+                                     0xb0, 0x69, 0xbd, 0x1a, 0x9b, 0x6f, 0xe5, 0xc4, 0xbc, 0xc3,
+                                     0xc3, 0x1f, 0x8e, 0x67, 0xe8, 0x31, 0xd0, 0x7a, 0x61, 0x08,
+                                     0x5f, 0x68];
+
+    const V1_SERVER_IV: [u8; 32] = [0x51, 0x97, 0xfc, 0x1e, 0x25, 0xb4, 0x1f, 0xe3, 0x6f, 0x18,
+                                    0xb5, 0xa3, 0xa8, 0xb2, 0xb3, 0x6c, 0xb2, 0xcb, 0x06, 0x1f,
+                                    0x1f, 0x15, 0x7b, 0x35, 0x14, 0xfe, 0x42, 0xe7, 0x4f, 0xb5,
+                                    0x83, 0x59];
+
+
+    // A message sent by the server (from grammers); the container of a `new_session_created`
+    // and a `pong`
+    const SERVER_AUTH_KEY: [u8; 256] = [0x5d, 0x2e, 0x7d, 0x65, 0xf4, 0x9e, 0xc2, 0x8b, 0xd0, 0x29,
+                                        0xa8, 0x87, 0x61, 0xea, 0x27, 0xb8, 0xa4, 0xc7, 0x9f, 0x12,
+                                        0x22, 0x65, 0x25, 0x44, 0x3e, 0x7d, 0x7c, 0x59, 0x6e, 0xf3,
+                                        0x30, 0x35, 0x30, 0xdb, 0x21, 0x07, 0xe8, 0x9a, 0xa9, 0x97,
+                                        0xc7, 0xa0, 0x16, 0x4a, 0xb6, 0x94, 0x18, 0x7a, 0xde, 0xff,
+                                        0x15, 0x6b, 0xd6, 0xef, 0x71, 0x18, 0xa1, 0x96, 0x23, 0x47,
+                                        0x75, 0x3c, 0x0e, 0x7e, 0x89, 0xa0, 0x35, 0x4b, 0x8e, 0xc3,
+                                        0x64, 0xf9, 0x99, 0x7e, 0x71, 0xbc, 0x69, 0x23, 0xfb, 0x86,
+                                        0xe8, 0xe4, 0x34, 0x91, 0xe0, 0x10, 0x60, 0x6a, 0x6c, 0xe8,
+                                        0x45, 0xe2, 0xfa, 0x01, 0x94, 0x09, 0x77, 0xef, 0x0a, 0xa3,
+                                        0x2a, 0xdf, 0x5a, 0x97, 0xdb, 0xf6, 0xd4, 0x28, 0xec, 0x04,
+                                        0x34, 0xd7, 0x17, 0xa2, 0xd3, 0xad, 0x19, 0x62, 0x2c, 0xc0,
+                                        0x58, 0x87, 0x64, 0x21, 0x13, 0xc7, 0x96, 0x5f, 0xfb, 0x86,
+                                        0x2a, 0x3e, 0x3c, 0xcb, 0x0a, 0xb9, 0x5a, 0xdd, 0xda, 0x57,
+                                        0xf8, 0x92, 0x45, 0xdb, 0xd7, 0x6b, 0x49, 0x23, 0x48, 0xf8,
+                                        0xe9, 0x4b, 0xd5, 0xa7, 0xc0, 0xe0, 0xb8, 0x48, 0x08, 0x52,
+                                        0x3c, 0xfd, 0x1e, 0xa8, 0x0b, 0x32, 0xfe, 0x9a, 0xd1, 0x98,
+                                        0xbc, 0x2e, 0x10, 0x3f, 0xce, 0xb7, 0xd5, 0x24, 0x92, 0xec,
+                                        0xc0, 0x27, 0x3a, 0x28, 0x67, 0x4b, 0xc9, 0x23, 0xee, 0xe5,
+                                        0x92, 0x65, 0xab, 0x17, 0xa0, 0x02, 0xdf, 0x1f, 0x4a, 0xa2,
+                                        0xc5, 0x9b, 0x81, 0x9a, 0x5e, 0x5e, 0x1d, 0x10, 0x5e, 0xc1,
+                                        0x17, 0x33, 0x6f, 0x5c, 0x76, 0xc6, 0xb1, 0x87, 0x03, 0x7d,
+                                        0x4b, 0x42, 0x70, 0xce, 0xe9, 0xcc, 0x21, 0x07, 0x1d, 0x97,
+                                        0xe9, 0xbc, 0xa2, 0x20, 0xc6, 0xd7, 0xb0, 0x1b, 0x99, 0x8c,
+                                        0xf2, 0xe5, 0xcd, 0xb9, 0xa5, 0x0e, 0xcd, 0xa1, 0x85, 0x2a,
+                                        0x36, 0xe6, 0x35, 0x69, 0x0c, 0x8e];
+
+    const SERVER_MESSAGE: [u8; 168] = [0x7a, 0x71, 0x83, 0xc2, 0xc1, 0x0e, 0x4f, 0x4d, 0xf9, 0x45,
+                                       0xfa, 0x9a, 0x9a, 0xbd, 0x35, 0xe7, 0xc3, 0x84, 0x0b, 0x61,
+                                       0xf0, 0x45, 0x30, 0x4f, 0x39, 0x67, 0x4c, 0x19, 0xc0, 0xe2,
+                                       0x09, 0x78, 0x4f, 0x50, 0xf6, 0x22, 0x6a, 0x07, 0x35, 0x29,
+                                       0xd6, 0x75, 0xc9, 0x2c, 0xbf, 0x0b, 0xfa, 0x8c, 0x99, 0xa7,
+                                       0x9b, 0x3f, 0x39, 0xc7, 0x2a, 0x5d, 0x9a, 0x02, 0x6d, 0x43,
+                                       0x1a, 0xb7, 0x40, 0x7c, 0xa0, 0x4e, 0xcc, 0x55, 0x18, 0x7d,
+                                       0x6c, 0x45, 0xf1, 0x78, 0x71, 0x52, 0x4e, 0xdd, 0x90, 0xce,
+                                       0xa0, 0x2e, 0xd7, 0x28, 0xe1, 0x4d, 0x7c, 0xb1, 0x8a, 0xea,
+                                       0x2a, 0x63, 0x61, 0x58, 0xf0, 0x94, 0x59, 0xa9, 0x43, 0x77,
+                                       0x10, 0xd8, 0x94, 0xc7, 0x9f, 0x36, 0x8c, 0x4e, 0x81, 0x64,
+                                       0xb7, 0x64, 0x7e, 0xa9, 0x86, 0x12, 0xae, 0xfe, 0x94, 0x2c,
+                                       0x5d, 0x92, 0x12, 0x1a, 0xcb, 0x8d, 0xb0, 0x2d, 0xcc, 0xce,
+                                       0xb6, 0x6d, 0x0f, 0x87, 0x20, 0xac, 0x12, 0xa0, 0x6d, 0xb0,
+                                       0x58, 0x2b, 0xfd, 0x95, 0x5b, 0xe3, 0x4f, 0x36, 0x51, 0x18,
+                                       0xe3, 0xba, 0xb8, 0xcd, 0x08, 0x0c, 0xe6, 0xb4, 0x5b, 0x28,
+                                       0xea, 0xc5, 0x6d, 0xcd, 0x2a, 0x29, 0x37, 0x4e];
+
+    const SERVER_PLAINTEXT: [u8; 144] = [0xfc, 0x82, 0x6a, 0x02, 0x24, 0x8b, 0x28, 0xfd, 0x60, 0xf2,
+                                         0xc4, 0x82, 0x24, 0x43, 0xad, 0x68, 0x01, 0xf0, 0xc1, 0xc2,
+                                         0x91, 0x8b, 0x30, 0x5e, 0x02, 0x00, 0x00, 0x00, 0x58, 0x00,
+                                         0x00, 0x00, 0xdc, 0xf8, 0xf1, 0x73, 0x02, 0x00, 0x00, 0x00,
+                                         0x01, 0xa8, 0xc1, 0xc2, 0x91, 0x8b, 0x30, 0x5e, 0x01, 0x00,
+                                         0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x08, 0x09, 0xc2, 0x9e,
+                                         0xc4, 0xfd, 0x33, 0xad, 0x91, 0x8b, 0x30, 0x5e, 0x18, 0xa8,
+                                         0x8e, 0xa6, 0x07, 0xee, 0x58, 0x16, 0xfc, 0x82, 0x6a, 0x02,
+                                         0x24, 0x8b, 0x28, 0xfd, 0x01, 0xcc, 0xc1, 0xc2, 0x91, 0x8b,
+                                         0x30, 0x5e, 0x02, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00,
+                                         0xc5, 0x73, 0x77, 0x34, 0xc4, 0xfd, 0x33, 0xad, 0x91, 0x8b,
+                                         0x30, 0x5e, 0x64, 0x08, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                         0xfc, 0xe6, 0x67, 0x04, 0xa3, 0xcd, 0x8e, 0xe9, 0xd0, 0xae,
+                                         0x6f, 0xab, 0x67, 0x2c, 0x60, 0xc0, 0x4a, 0x3f, 0x1f, 0xd4,
+                                         0x49, 0x0e, 0x51, 0xf6];
+
+    const SALT: i64 = 0x0123456789abcdef;
+
+    fn auth_key() -> [u8; 256] {
+        let mut auth_key = [0; 256];
+        for (index, byte) in auth_key.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        auth_key
+    }
+
+    fn msg_key() -> [u8; 16] {
+        let mut msg_key = [0; 16];
+        for (index, byte) in msg_key.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        msg_key
+    }
+
+    fn session(auth_key: [u8; 256], version: Version) -> EncryptedSession {
+        let mut session =
+            EncryptedSession::with_message_id(AuthKey::new(auth_key), SALT, MessageIdGenerator::new());
+        session.set_version(version);
+
+        session
+    }
+
+    /// Decrypt a message sent by the client as the server would; the salt, session_id,
+    /// message identifier and seq_no are returned along with the data
+    fn server_open(session: &EncryptedSession, message: &[u8]) -> (i64, i64, i64, i32, Vec<u8>) {
+        assert_eq!(LittleEndian::read_i64(message), session.auth_key.id());
+
+        let mut msg_key = [0; 16];
+        msg_key.copy_from_slice(&message[8..HEADER_LEN]);
+
+        let mut plaintext = message[HEADER_LEN..].to_vec();
+        session.cipher(&msg_key, Direction::ClientToServer).decrypt_in_place(&mut plaintext).unwrap();
+
+        let len = LittleEndian::read_i32(&plaintext[PLAINTEXT_HEADER_LEN - 4..]) as usize;
+        assert_eq!(session.msg_key(&plaintext, len, Direction::ClientToServer), msg_key);
+
+        let (min_padding_len, max_padding_len) = session.version.padding_len();
+        let padding_len = plaintext.len() - PLAINTEXT_HEADER_LEN - len;
+        assert!(padding_len >= min_padding_len && padding_len <= max_padding_len);
+
+        (LittleEndian::read_i64(&plaintext),
+         LittleEndian::read_i64(&plaintext[8..]),
+         LittleEndian::read_i64(&plaintext[16..]),
+         LittleEndian::read_i32(&plaintext[24..]),
+         plaintext[PLAINTEXT_HEADER_LEN..PLAINTEXT_HEADER_LEN + len].to_vec())
+    }
+
+    /// Encrypt a message as the server would; followed by `padding_len` bytes of padding
+    fn server_seal(session: &EncryptedSession,
+                   session_id: i64,
+                   data: &[u8],
+                   padding_len: usize)
+                   -> Vec<u8> {
+        let mut plaintext = Vec::new();
+        plaintext.write_i64::<LittleEndian>(SALT).unwrap();
+        plaintext.write_i64::<LittleEndian>(session_id).unwrap();
+        plaintext.write_i64::<LittleEndian>(1 << 32 | 1).unwrap();
+        plaintext.write_i32::<LittleEndian>(1).unwrap();
+        plaintext.write_i32::<LittleEndian>(data.len() as i32).unwrap();
+        plaintext.extend_from_slice(data);
+        plaintext.extend(vec![0x5a; padding_len]);
+
+        let msg_key = session.msg_key(&plaintext, data.len(), Direction::ServerToClient);
+        session.cipher(&msg_key, Direction::ServerToClient).encrypt_in_place(&mut plaintext).unwrap();
+
+        let mut message = Vec::new();
+        message.write_i64::<LittleEndian>(session.auth_key.id()).unwrap();
+        message.extend_from_slice(&msg_key);
+        message.extend_from_slice(&plaintext);
+
+        message
+    }
+
+    fn assert_rejected<T: ::std::fmt::Debug>(result: Result<T>, expected: &ErrorKind) {
+        match result {
+            Err(Error(ref kind, _)) if kind.to_string() == expected.to_string() => {}
+            result => panic!("expected {}; found {:?}", expected, result),
+        }
+    }
+
+    #[test]
+    fn derive_v2_keys() {
+        let session = session(auth_key(), Version::V2);
+
+        assert_eq!(session.key_iv(&msg_key(), Direction::ClientToServer),
+                   (V2_CLIENT_KEY, V2_CLIENT_IV));
+        assert_eq!(session.key_iv(&msg_key(), Direction::ServerToClient),
+                   (V2_SERVER_KEY, V2_SERVER_IV));
+    }
+
+    #[test]
+    fn derive_v1_keys() {
+        let session = session(auth_key(), Version::V1);
+
+        assert_eq!(session.key_iv(&msg_key(), Direction::ClientToServer),
+                   (V1_CLIENT_KEY, V1_CLIENT_IV));
+        assert_eq!(session.key_iv(&msg_key(), Direction::ServerToClient),
+                   (V1_SERVER_KEY, V1_SERVER_IV));
+    }
+
+    #[test]
+    fn open_server_message() {
+        let mut session = session(SERVER_AUTH_KEY, Version::V2);
+        session.session_id = LittleEndian::read_i64(&SERVER_PLAINTEXT[8..]);
+
+        let message = session.open::<Object>(&SERVER_MESSAGE).unwrap();
+        assert_eq!(message.message_id, LittleEndian::read_i64(&SERVER_PLAINTEXT[16..]));
+        assert_eq!(message.seq_no, LittleEndian::read_i32(&SERVER_PLAINTEXT[24..]));
+        assert_eq!(message.body.0, &SERVER_PLAINTEXT[32..120]);
+
+        let messages = container::unpack::<Object>(&message.body).unwrap();
+        let ids = messages.iter().map(|message| message.body.constructor_id()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(0x9ec20908), Some(0x347773c5)]);
+    }
+
+    #[test]
+    fn round_trip_v2() {
+        let mut session = session(auth_key(), Version::V2);
+
+        let (message_id, message) = session.seal(&schema::Ping { ping_id: 7 }, true).unwrap();
+        let (salt, session_id, sent_message_id, seq_no, data) = server_open(&session, &message);
+        assert_eq!((salt, session_id, sent_message_id, seq_no),
+                   (SALT, session.session_id(), message_id, 1));
+        assert_eq!(data, Object::encode(&schema::Ping { ping_id: 7 }).unwrap().0);
+
+        let pong = Object::encode(&schema::Pong {
+                msg_id: message_id,
+                ping_id: 7,
+            })
+            .unwrap();
+
+        for &padding_len in &[12, 28, 1020] {
+            let reply = server_seal(&session, session.session_id(), &pong.0, padding_len);
+            let reply = session.open::<schema::Pong>(&reply).unwrap();
+
+            assert_eq!(reply.message_id, 1 << 32 | 1);
+            assert_eq!((reply.body.msg_id, reply.body.ping_id), (message_id, 7));
+        }
+    }
+
+    #[test]
+    fn round_trip_v1() {
+        let mut session = session(auth_key(), Version::V1);
+
+        let (message_id, message) = session.seal(&schema::Ping { ping_id: 7 }, true).unwrap();
+        let (salt, session_id, sent_message_id, seq_no, data) = server_open(&session, &message);
+        assert_eq!((salt, session_id, sent_message_id, seq_no),
+                   (SALT, session.session_id(), message_id, 1));
+        assert_eq!(data, Object::encode(&schema::Ping { ping_id: 7 }).unwrap().0);
+
+        // msg_key covers the data only; the padding is up to 15 bytes
+        let pong = Object::encode(&schema::Pong {
+                msg_id: message_id,
+                ping_id: 7,
+            })
+            .unwrap();
+
+        let reply = server_seal(&session, session.session_id(), &pong.0, 12);
+        let reply = session.open::<schema::Pong>(&reply).unwrap();
+        assert_eq!((reply.body.msg_id, reply.body.ping_id), (message_id, 7));
+
+        let reply = server_seal(&session, session.session_id(), &pong.0, 28);
+        assert_rejected(session.open::<Object>(&reply), &ErrorKind::InvalidPaddingLength(28));
+
+        let mut tampered = server_seal(&session, session.session_id(), &pong.0, 12);
+        tampered[8] ^= 1;
+        assert_rejected(session.open::<Object>(&tampered), &ErrorKind::InvalidMsgKey);
+    }
+
+    #[test]
+    fn reject_session_id() {
+        let session = session(auth_key(), Version::V2);
+
+        let reply = server_seal(&session, session.session_id().wrapping_add(1), &[1; 24], 24);
+        assert_rejected(session.open::<Object>(&reply),
+                        &ErrorKind::UnexpectedSessionId(session.session_id().wrapping_add(1)));
+    }
+
+    #[test]
+    fn reject_msg_key() {
+        let session = session(auth_key(), Version::V2);
+        let reply = server_seal(&session, session.session_id(), &[1; 24], 24);
+
+        // Another msg_key
+        let mut tampered = reply.clone();
+        tampered[8] ^= 1;
+        assert_rejected(session.open::<Object>(&tampered), &ErrorKind::InvalidMsgKey);
+
+        // Another last block (of the padding)
+        let mut tampered = reply.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_rejected(session.open::<Object>(&tampered), &ErrorKind::InvalidMsgKey);
+    }
+
+    #[test]
+    fn reject_padding_len() {
+        let session = session(auth_key(), Version::V2);
+
+        for &padding_len in &[8, 1032] {
+            let reply = server_seal(&session, session.session_id(), &[1; 24], padding_len);
+            assert_rejected(session.open::<Object>(&reply),
+                            &ErrorKind::InvalidPaddingLength(padding_len));
+        }
+    }
+
+    #[test]
+    fn resend_container() {
+        let mut session = session(auth_key(), Version::V2);
+
+        let first = session.pack(&schema::Ping { ping_id: 1 }, true).unwrap();
+        let second = session.pack(&schema::MsgsAck { msg_ids: vec![1] }, false).unwrap();
+        let ids = [first.msg_id, second.msg_id];
+        let (container_id, _) = session.seal_container(vec![first, second]).unwrap();
+
+        session.set_salt(SALT + 1);
+        let resent = session.resend(container_id).unwrap().unwrap();
+        assert!(resent.message_id > container_id);
+        assert_eq!(resent.renumbered.len(), 2);
+
+        let (salt, _, message_id, _, data) = server_open(&session, &resent.message);
+        assert_eq!((salt, message_id), (SALT + 1, resent.message_id));
+
+        let container = Object(data).decode::<schema::MessageContainer>().unwrap();
+        for ((message, &(msg_id, new_msg_id)), &id) in
+            container.messages.iter().zip(&resent.renumbered).zip(&ids) {
+            assert_eq!(msg_id, id);
+            assert!(new_msg_id > container_id && new_msg_id < resent.message_id);
+            assert_eq!(message.msg_id, new_msg_id);
+        }
+
+        // Content-related messages keep an odd seq_no and the others an even one
+        assert_eq!(container.messages[0].seqno, 3);
+        assert_eq!(container.messages[1].seqno, 4);
+
+        // The container is resent once
+        assert!(session.resend(container_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn pack_large_data() {
+        let compression = Compression::default();
+        let values = (0..200).collect::<Vec<i64>>();
+        let data = Object::encode(&values).unwrap().0;
+        assert!(data.len() >= compression.threshold);
+
+        let packed = compression.pack(data.clone()).unwrap();
+        assert!(packed.len() < data.len());
+        assert_eq!(Object(packed.clone()).constructor_id(), Some(tl::GZIP_PACKED));
+        assert_eq!(Object(packed).decode::<Vec<i64>>().unwrap(), values);
+    }
+
+    #[test]
+    fn keep_small_data() {
+        let compression = Compression::default();
+
+        // Under the threshold
+        let data = Object::encode(&(0..10).collect::<Vec<i64>>()).unwrap().0;
+        assert_eq!(compression.pack(data.clone()).unwrap(), data);
+
+        // Packing saves too little
+        let data = (0..compression.threshold).map(|_| rand::random()).collect::<Vec<u8>>();
+        assert_eq!(compression.pack(data.clone()).unwrap(), data);
+    }
+}
@@ -0,0 +1,170 @@
+use crypto::aessafe::{AesSafe256Decryptor, AesSafe256Encryptor};
+use crypto::symmetriccipher::{BlockDecryptor, BlockEncryptor};
+use errors::*;
+
+/// Size of an AES block
+pub const BLOCK_LEN: usize = 16;
+
+/// AES-256 in Infinite Garble Extension (IGE) mode
+///
+/// The initialization vector is 32 bytes; the first 16 stand in for the ciphertext block
+/// before the first block and the last 16 for the plaintext block before it.
+// https://core.telegram.org/mtproto/description#defining-aes-key-and-initialization-vector
+pub struct AesIge {
+    encryptor: AesSafe256Encryptor,
+    decryptor: AesSafe256Decryptor,
+    iv: [u8; 32],
+}
+
+impl AesIge {
+    pub fn new(key: &[u8; 32], iv: &[u8; 32]) -> AesIge {
+        AesIge {
+            encryptor: AesSafe256Encryptor::new(key),
+            decryptor: AesSafe256Decryptor::new(key),
+            iv: *iv,
+        }
+    }
+
+    /// Encrypt the data in place; its length must be a multiple of 16
+    pub fn encrypt_in_place(&self, data: &mut [u8]) -> Result<()> {
+        check_len(data)?;
+
+        let (mut previous_ciphertext, mut previous_plaintext) = self.split_iv();
+        let mut input = [0; BLOCK_LEN];
+        let mut output = [0; BLOCK_LEN];
+
+        // c_i = E(p_i ^ c_i-1) ^ p_i-1
+        for block in data.chunks_mut(BLOCK_LEN) {
+            xor(&mut input, block, &previous_ciphertext);
+            self.encryptor.encrypt_block(&input, &mut output);
+
+            let plaintext = previous_plaintext;
+            previous_plaintext.copy_from_slice(block);
+
+            xor(block, &output, &plaintext);
+            previous_ciphertext.copy_from_slice(block);
+        }
+
+        Ok(())
+    }
+
+    /// Decrypt the data in place; its length must be a multiple of 16
+    pub fn decrypt_in_place(&self, data: &mut [u8]) -> Result<()> {
+        check_len(data)?;
+
+        let (mut previous_ciphertext, mut previous_plaintext) = self.split_iv();
+        let mut input = [0; BLOCK_LEN];
+        let mut output = [0; BLOCK_LEN];
+
+        // p_i = D(c_i ^ p_i-1) ^ c_i-1
+        for block in data.chunks_mut(BLOCK_LEN) {
+            xor(&mut input, block, &previous_plaintext);
+            self.decryptor.decrypt_block(&input, &mut output);
+
+            let ciphertext = previous_ciphertext;
+            previous_ciphertext.copy_from_slice(block);
+
+            xor(block, &output, &ciphertext);
+            previous_plaintext.copy_from_slice(block);
+        }
+
+        Ok(())
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut result = data.to_vec();
+        self.encrypt_in_place(&mut result)?;
+
+        Ok(result)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut result = data.to_vec();
+        self.decrypt_in_place(&mut result)?;
+
+        Ok(result)
+    }
+
+    fn split_iv(&self) -> ([u8; BLOCK_LEN], [u8; BLOCK_LEN]) {
+        let mut first = [0; BLOCK_LEN];
+        let mut second = [0; BLOCK_LEN];
+        first.copy_from_slice(&self.iv[..BLOCK_LEN]);
+        second.copy_from_slice(&self.iv[BLOCK_LEN..]);
+
+        (first, second)
+    }
+}
+
+fn check_len(data: &[u8]) -> Result<()> {
+    if data.len() % BLOCK_LEN != 0 {
+        return Err(ErrorKind::InvalidBlockLength(data.len()).into());
+    }
+
+    Ok(())
+}
+
+fn xor(output: &mut [u8], a: &[u8], b: &[u8]) {
+    for ((output, a), b) in output.iter_mut().zip(a).zip(b) {
+        *output = a ^ b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::*;
+    use super::AesIge;
+
+    // Test vectors of AES-256-IGE as used by Telethon (and grammers); the key, the IV and
+    // the input are all the bytes 0, 1, .., 31
+    const ENCRYPTED: [u8; 32] = [0xe2, 0x81, 0x12, 0xa5, 0x3e, 0x5c, 0x89, 0xc7, 0xb1, 0xea, 0x80,
+                                 0x71, 0xc1, 0x33, 0x69, 0x9f, 0xd4, 0xe8, 0x6b, 0x26, 0xc4, 0xba,
+                                 0xc9, 0xfc, 0x5a, 0xf1, 0xab, 0x8c, 0xe2, 0x7a, 0x44, 0xa4];
+
+    const DECRYPTED: [u8; 32] = [0xe5, 0x77, 0x7a, 0xfa, 0xcd, 0x7b, 0x2c, 0x16, 0xf7, 0xac, 0x40,
+                                 0xca, 0xe6, 0x1e, 0xf6, 0x03, 0xfe, 0xe6, 0x09, 0x8f, 0xb8, 0xa8,
+                                 0x86, 0x0a, 0xb9, 0xee, 0x67, 0x2c, 0xd7, 0xe5, 0xba, 0xcc];
+
+    fn sequence() -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn encrypt() {
+        let ige = AesIge::new(&sequence(), &sequence());
+
+        assert_eq!(ige.encrypt(&sequence()).unwrap(), ENCRYPTED.to_vec());
+        assert_eq!(ige.decrypt(&ENCRYPTED).unwrap(), sequence().to_vec());
+    }
+
+    #[test]
+    fn decrypt() {
+        let ige = AesIge::new(&sequence(), &sequence());
+
+        assert_eq!(ige.decrypt(&sequence()).unwrap(), DECRYPTED.to_vec());
+        assert_eq!(ige.encrypt(&DECRYPTED).unwrap(), sequence().to_vec());
+    }
+
+    #[test]
+    fn reject_partial_block() {
+        let ige = AesIge::new(&sequence(), &sequence());
+
+        for len in &[1, 15, 17, 31] {
+            let mut data = vec![0; *len];
+
+            match ige.encrypt_in_place(&mut data) {
+                Err(Error(ErrorKind::InvalidBlockLength(found), _)) => assert_eq!(found, *len),
+                _ => panic!("{} bytes are encrypted", len),
+            }
+
+            match ige.decrypt_in_place(&mut data) {
+                Err(Error(ErrorKind::InvalidBlockLength(found), _)) => assert_eq!(found, *len),
+                _ => panic!("{} bytes are decrypted", len),
+            }
+        }
+    }
+}
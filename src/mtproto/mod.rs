@@ -0,0 +1,38 @@
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use crypto::sha2::Sha256;
+
+pub mod auth;
+pub mod container;
+pub mod encrypted;
+pub mod ige;
+pub mod msg_id;
+pub mod plain;
+pub mod rpc;
+pub mod rsa;
+pub mod salt;
+pub mod schema;
+
+/// SHA1 of the parts one after another
+fn sha1(parts: &[&[u8]]) -> [u8; 20] {
+    let mut sha1 = Sha1::new();
+    for part in parts {
+        sha1.input(part);
+    }
+
+    let mut hash = [0; 20];
+    sha1.result(&mut hash);
+    hash
+}
+
+/// SHA256 of the parts one after another
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut sha256 = Sha256::new();
+    for part in parts {
+        sha256.input(part);
+    }
+
+    let mut hash = [0; 32];
+    sha256.result(&mut hash);
+    hash
+}
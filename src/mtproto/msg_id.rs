@@ -0,0 +1,165 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time; can be replaced to control the generated message identifiers
+pub trait Clock {
+    /// Time elapsed since the UNIX epoch
+    fn now(&self) -> Duration;
+}
+
+/// The local system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0))
+    }
+}
+
+/// Generator of message identifiers for messages sent by the client
+///
+/// A message identifier is approximately equal to the server time the message was created at,
+/// multiplied by 2^32; identifiers created by the client are divisible by 4 and must increase
+/// monotonically within a session.
+// https://core.telegram.org/mtproto/description#message-identifier-msg-id
+#[derive(Debug)]
+pub struct MessageIdGenerator<C = SystemClock> {
+    clock: C,
+
+    /// Difference in seconds between the server time and the local time
+    time_offset: i32,
+
+    /// Last generated identifier
+    last: i64,
+}
+
+impl MessageIdGenerator<SystemClock> {
+    pub fn new() -> MessageIdGenerator<SystemClock> {
+        MessageIdGenerator::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> MessageIdGenerator<C> {
+    pub fn with_clock(clock: C) -> MessageIdGenerator<C> {
+        MessageIdGenerator {
+            clock: clock,
+            time_offset: 0,
+            last: 0,
+        }
+    }
+
+    pub fn time_offset(&self) -> i32 {
+        self.time_offset
+    }
+
+    pub fn set_time_offset(&mut self, time_offset: i32) {
+        self.time_offset = time_offset;
+    }
+
+    /// Current server time in seconds since the UNIX epoch; the local time corrected
+    /// by the time offset
+    pub fn server_time(&self) -> i32 {
+        (self.clock.now().as_secs() as i64 + self.time_offset as i64) as i32
+    }
+
+    /// Correct the time offset from the identifier of a message sent by the server
+    ///
+    /// Should be done after a `bad_msg_notification` with error code 16 (msg_id too low)
+    /// or 17 (msg_id too high), with the identifier of the message that carried it.
+    pub fn update_time_offset(&mut self, server_msg_id: i64) {
+        let server_time = server_msg_id >> 32;
+        let local_time = self.clock.now().as_secs() as i64;
+
+        self.time_offset = (server_time - local_time) as i32;
+    }
+
+    /// Generate the next message identifier
+    pub fn next_id(&mut self) -> i64 {
+        let now = self.clock.now();
+        let seconds = now.as_secs() as i64 + self.time_offset as i64;
+
+        // The fraction of the second in units of 2^-32 seconds; divisible by 4
+        let fraction = (now.subsec_nanos() as u64 * (1 << 32) / 1_000_000_000) as i64 & !3;
+        let mut id = (seconds << 32) | fraction;
+
+        // Identifiers must be strictly increasing even if the clock is not
+        if id <= self.last {
+            id = self.last + 4;
+        }
+
+        self.last = id;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use super::{Clock, MessageIdGenerator};
+
+    /// A clock standing still at the time it is set to
+    #[derive(Clone, Default)]
+    struct FakeClock(Rc<Cell<Duration>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn fraction_of_second() {
+        let clock = FakeClock::default();
+        let mut message_id = MessageIdGenerator::with_clock(clock.clone());
+
+        clock.0.set(Duration::new(1000, 500_000_000));
+        assert_eq!(message_id.next_id(), 1000 << 32 | 1 << 31);
+
+        clock.0.set(Duration::new(1001, 999_999_999));
+        assert_eq!(message_id.next_id(), 1001 << 32 | 0xffff_fff8);
+    }
+
+    #[test]
+    fn increase_and_divisible_by_4() {
+        let clock = FakeClock::default();
+        let mut message_id = MessageIdGenerator::with_clock(clock.clone());
+
+        // Forward, repeated and backwards
+        let times = [(1000, 1), (1000, 3), (1000, 3), (1000, 123_456_789), (999, 0), (1000, 7),
+                     (1001, 0), (1001, 0)];
+
+        let mut last = 0;
+        for &(seconds, nanos) in &times {
+            clock.0.set(Duration::new(seconds, nanos));
+
+            let id = message_id.next_id();
+            assert!(id > last, "{} after {}", id, last);
+            assert_eq!(id % 4, 0);
+
+            last = id;
+        }
+    }
+
+    #[test]
+    fn apply_time_offset() {
+        let clock = FakeClock::default();
+        clock.0.set(Duration::new(1000, 0));
+
+        let mut message_id = MessageIdGenerator::with_clock(clock.clone());
+        message_id.update_time_offset(1030 << 32 | 1);
+        assert_eq!(message_id.time_offset(), 30);
+        assert_eq!(message_id.server_time(), 1030);
+        assert_eq!(message_id.next_id(), 1030 << 32);
+
+        message_id.update_time_offset(1010 << 32 | 1);
+        assert_eq!(message_id.time_offset(), 10);
+
+        // Behind the last identifier
+        assert_eq!(message_id.next_id(), 1030 << 32 | 4);
+
+        clock.0.set(Duration::new(1030, 0));
+        assert_eq!(message_id.next_id(), 1040 << 32);
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::Write;
+use serde::{Serialize, Deserialize};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use errors::*;
+use ser::Serializer;
+use de::Deserializer;
+
+/// Length of the envelope before the message data
+///  - auth_key_id: i64
+///  - message_id: i64
+///  - message_data_length: i32
+const HEADER_LEN: usize = 8 + 8 + 4;
+
+/// An unencrypted message; only used to create an authorization key
+// https://core.telegram.org/mtproto/description#unencrypted-message
+#[derive(Debug)]
+pub struct Message<T> {
+    pub message_id: i64,
+    pub body: T,
+}
+
+impl<T> Message<T> {
+    pub fn new(message_id: i64, body: T) -> Message<T> {
+        Message {
+            message_id: message_id,
+            body: body,
+        }
+    }
+}
+
+impl<T: Serialize> Message<T> {
+    /// Wrap the body in the envelope
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.body.serialize(&mut Serializer::new(&mut data))?;
+
+        let mut buffer = Vec::with_capacity(HEADER_LEN + data.len());
+
+        // An unencrypted message is identified by an auth_key_id of 0
+        buffer.write_i64::<LittleEndian>(0)?;
+        buffer.write_i64::<LittleEndian>(self.message_id)?;
+        buffer.write_i32::<LittleEndian>(data.len() as i32)?;
+        buffer.write_all(&data)?;
+
+        Ok(buffer)
+    }
+}
+
+impl<T: Deserialize> Message<T> {
+    /// Unwrap the body from the envelope
+    pub fn from_slice(mut buffer: &[u8]) -> Result<Message<T>> {
+        if buffer.len() < HEADER_LEN {
+            return Err(ErrorKind::InvalidMessageLength(HEADER_LEN, buffer.len()).into());
+        }
+
+        let auth_key_id = buffer.read_i64::<LittleEndian>()?;
+        if auth_key_id != 0 {
+            return Err(ErrorKind::UnexpectedAuthKeyId(auth_key_id).into());
+        }
+
+        let message_id = buffer.read_i64::<LittleEndian>()?;
+
+        // The length must cover the rest of the message exactly
+        let len = buffer.read_i32::<LittleEndian>()?;
+        if len < 0 || len as usize != buffer.len() {
+            return Err(ErrorKind::InvalidMessageLength(len as usize, buffer.len()).into());
+        }
+
+        let body = T::deserialize(&mut Deserializer::new(&mut buffer))?;
+        if !buffer.is_empty() {
+            return Err(ErrorKind::InvalidMessageLength(len as usize, len as usize - buffer.len())
+                .into());
+        }
+
+        Ok(Message::new(message_id, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::*;
+    use tl::Int128;
+    use mtproto::schema;
+    use super::Message;
+
+    // req_pq#60469778 nonce:int128 in an unencrypted message
+    const REQ_PQ: [u8; 40] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+                              0x00, 0x01, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x78, 0x97,
+                              0x46, 0x60, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+                              0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01];
+
+    fn assert_invalid_length<T: ::std::fmt::Debug>(result: Result<T>) {
+        match result {
+            Err(Error(ErrorKind::InvalidMessageLength(..), _)) => {}
+            result => panic!("expected InvalidMessageLength; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn wrap_message() {
+        let message = Message::new(1 << 32 | 4, schema::ReqPq { nonce: Int128([1; 16]) });
+        assert_eq!(&message.to_vec().unwrap()[..], &REQ_PQ[..]);
+    }
+
+    #[test]
+    fn unwrap_message() {
+        let message = Message::<schema::ReqPq>::from_slice(&REQ_PQ).unwrap();
+        assert_eq!(message.message_id, 1 << 32 | 4);
+        assert_eq!(message.body.nonce, Int128([1; 16]));
+    }
+
+    #[test]
+    fn reject_auth_key_id() {
+        let mut buffer = REQ_PQ;
+        buffer[0] = 1;
+
+        match Message::<schema::ReqPq>::from_slice(&buffer) {
+            Err(Error(ErrorKind::UnexpectedAuthKeyId(1), _)) => {}
+            result => panic!("expected UnexpectedAuthKeyId; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reject_length() {
+        // Shorter than the envelope
+        assert_invalid_length(Message::<schema::ReqPq>::from_slice(&REQ_PQ[..19]));
+
+        // The length is off from the data
+        let mut buffer = REQ_PQ.to_vec();
+        buffer[16] = 0x13;
+        assert_invalid_length(Message::<schema::ReqPq>::from_slice(&buffer));
+
+        buffer[16] = 0xff;
+        buffer[19] = 0xff;
+        assert_invalid_length(Message::<schema::ReqPq>::from_slice(&buffer));
+
+        // The body does not take up the whole length
+        let mut buffer = REQ_PQ.to_vec();
+        buffer[16] = 0x18;
+        buffer.extend_from_slice(&[0; 4]);
+        assert_invalid_length(Message::<schema::ReqPq>::from_slice(&buffer));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use errors::*;
+use tl::{Object, RemoteCall};
+use super::encrypted::EncryptedSession;
+use super::msg_id::Clock;
+use super::schema;
+
+/// Constructor ID of `rpc_error#2144ca19 error_code:int error_message:string = RpcError`
+pub const RPC_ERROR: u32 = 0x2144ca19;
+
+/// Calls sent and not yet answered, by the identifier of the message they were sent in;
+/// each is answered (`rpc_result`) with its `RemoteCall::Return` or an error.
+///
+/// ```rust,ignore
+/// let (_, message) = pending.call(&mut session, &GetFutureSalts { num: 2 }, |result| {
+///     println!("{:?}", result);
+/// })?;
+///
+/// // .. then for every `rpc_result` received
+/// pending.handle(result)?;
+/// ```
+// https://core.telegram.org/mtproto/service_messages#rpc-result
+#[derive(Default)]
+pub struct PendingCalls {
+    pending: HashMap<i64, Box<FnOnce(Result<Object>)>>,
+}
+
+impl PendingCalls {
+    pub fn new() -> PendingCalls {
+        Default::default()
+    }
+
+    /// Encrypt a call to send to the server and wait on its result; the identifier of
+    /// the message is returned along with it
+    pub fn call<C, T, F>(&mut self,
+                         session: &mut EncryptedSession<C>,
+                         request: &T,
+                         callback: F)
+                         -> Result<(i64, Vec<u8>)>
+        where C: Clock,
+              T: RemoteCall,
+              F: FnOnce(Result<T::Return>) + 'static
+    {
+        let (message_id, message) = session.seal(request, true)?;
+        self.insert::<T, F>(message_id, callback);
+
+        Ok((message_id, message))
+    }
+
+    /// Wait on the result of a call sent in the message (`EncryptedSession::pack` to send
+    /// it in a container)
+    pub fn insert<T, F>(&mut self, message_id: i64, callback: F)
+        where T: RemoteCall,
+              F: FnOnce(Result<T::Return>) + 'static
+    {
+        self.pending.insert(message_id,
+                            Box::new(move |result: Result<Object>| {
+                                callback(result.and_then(|result| result.decode::<T::Return>()))
+                            }));
+    }
+
+    /// Stop waiting on the call sent in the message; it is not answered
+    pub fn remove(&mut self, message_id: i64) -> bool {
+        self.pending.remove(&message_id).is_some()
+    }
+
+    /// Wait on the result of a call under the identifier of the message it was resent in
+    pub fn rekey(&mut self, message_id: i64, new_message_id: i64) -> bool {
+        match self.pending.remove(&message_id) {
+            Some(callback) => {
+                self.pending.insert(new_message_id, callback);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, message_id: i64) -> bool {
+        self.pending.contains_key(&message_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Answer a pending call with its result; an `rpc_error` is answered as `ErrorKind::Rpc`
+    pub fn handle(&mut self, result: schema::RpcResult) -> Result<()> {
+        let callback = match self.pending.remove(&result.req_msg_id) {
+            Some(callback) => callback,
+            None => return Err(ErrorKind::UnexpectedReqMsgId(result.req_msg_id).into()),
+        };
+
+        if result.result.constructor_id() == Some(RPC_ERROR) {
+            let error = match result.result.decode::<schema::RpcError>() {
+                Ok(error) => {
+                    ErrorKind::Rpc(error.error_code, RpcError::parse(&error.error_message)).into()
+                }
+
+                Err(error) => error,
+            };
+
+            callback(Err(error));
+        } else {
+            callback(Ok(result.result));
+        }
+
+        Ok(())
+    }
+
+    /// Fail every pending call with the error (the connection was lost)
+    pub fn fail_all<F: Fn() -> Error>(&mut self, error: F) {
+        for (_, callback) in self.pending.drain() {
+            callback(Err(error()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use flate2;
+    use flate2::write::GzEncoder;
+    use serde::Serialize;
+    use serde::bytes::Bytes;
+    use errors::*;
+    use ser::Serializer;
+    use tl::{self, Object};
+    use mtproto::schema;
+    use super::{PendingCalls, RPC_ERROR};
+
+    /// The answer given to a callback
+    type Answer = Rc<RefCell<Option<Result<schema::Pong>>>>;
+
+    /// Wait on a `ping` sent in the message; the answer is kept
+    fn wait(pending: &mut PendingCalls, message_id: i64) -> Answer {
+        let answer = Answer::default();
+        let kept = answer.clone();
+        pending.insert::<schema::Ping, _>(message_id, move |result| {
+            *kept.borrow_mut() = Some(result);
+        });
+
+        answer
+    }
+
+    fn rpc_error(code: i32, message: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(RPC_ERROR).unwrap();
+        data.write_i32::<LittleEndian>(code).unwrap();
+        message.serialize(&mut Serializer::new(&mut data)).unwrap();
+
+        data
+    }
+
+    fn gzip_packed(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::Default);
+        encoder.write_all(data).unwrap();
+
+        let mut packed = Vec::new();
+        packed.write_u32::<LittleEndian>(tl::GZIP_PACKED).unwrap();
+        let packed_data = encoder.finish().unwrap();
+        Bytes::new(&packed_data).serialize(&mut Serializer::new(&mut packed)).unwrap();
+
+        packed
+    }
+
+    /// An `rpc_result` as received; packed results are inflated as it is decoded
+    fn rpc_result(req_msg_id: i64, result: &[u8]) -> schema::RpcResult {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(0xf35c6d01).unwrap();
+        data.write_i64::<LittleEndian>(req_msg_id).unwrap();
+        data.extend_from_slice(result);
+
+        Object(data).decode().unwrap()
+    }
+
+    #[test]
+    fn answer_result() {
+        let mut pending = PendingCalls::new();
+        let answer = wait(&mut pending, 4);
+
+        let pong = Object::encode(&schema::Pong { msg_id: 4, ping_id: 10 }).unwrap();
+        pending.handle(rpc_result(4, &pong.0)).unwrap();
+
+        assert!(pending.is_empty());
+        let answer = answer.borrow_mut().take();
+        match answer {
+            Some(Ok(pong)) => assert_eq!((pong.msg_id, pong.ping_id), (4, 10)),
+            answer => panic!("expected a pong; found {:?}", answer),
+        }
+    }
+
+    #[test]
+    fn answer_rpc_error() {
+        let mut pending = PendingCalls::new();
+        let plain = wait(&mut pending, 4);
+        let packed = wait(&mut pending, 8);
+
+        pending.handle(rpc_result(4, &rpc_error(420, "FLOOD_WAIT_30"))).unwrap();
+        pending.handle(rpc_result(8, &gzip_packed(&rpc_error(303, "PHONE_MIGRATE_4")))).unwrap();
+
+        let plain = plain.borrow_mut().take();
+        match plain {
+            Some(Err(Error(ErrorKind::Rpc(420, RpcError::FloodWait(30)), _))) => {}
+            answer => panic!("expected FLOOD_WAIT_30; found {:?}", answer),
+        }
+
+        let packed = packed.borrow_mut().take();
+        match packed {
+            Some(Err(Error(ErrorKind::Rpc(303, RpcError::PhoneMigrate(4)), _))) => {}
+            answer => panic!("expected PHONE_MIGRATE_4; found {:?}", answer),
+        }
+    }
+
+    #[test]
+    fn reject_unexpected_req_msg_id() {
+        let mut pending = PendingCalls::new();
+        let answer = wait(&mut pending, 4);
+
+        let pong = Object::encode(&schema::Pong { msg_id: 8, ping_id: 10 }).unwrap();
+        match pending.handle(rpc_result(8, &pong.0)) {
+            Err(Error(ErrorKind::UnexpectedReqMsgId(8), _)) => {}
+            result => panic!("expected UnexpectedReqMsgId; found {:?}", result),
+        }
+
+        // The pending call is still waiting
+        assert!(pending.contains(4));
+        assert!(answer.borrow().is_none());
+    }
+}
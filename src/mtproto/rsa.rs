@@ -0,0 +1,69 @@
+use byteorder::{ByteOrder, LittleEndian};
+use num_bigint::BigUint;
+use rand::{self, Rng};
+use serde::bytes::ByteBuf;
+use serde::Serialize;
+use errors::*;
+use ser::Serializer;
+use super::sha1;
+
+/// Length of the data encrypted with a server public key; `sha1(data) + data + padding`
+const DATA_WITH_HASH_LEN: usize = 255;
+
+/// A public RSA key of the server; used to encrypt `p_q_inner_data` during
+/// the creation of an authorization key.
+// https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication
+#[derive(Debug, Clone)]
+pub struct RsaPublicKey {
+    n: BigUint,
+    e: BigUint,
+    fingerprint: i64,
+}
+
+impl RsaPublicKey {
+    /// Create a key from its modulus and exponent (big-endian)
+    pub fn new(n: &[u8], e: &[u8]) -> Result<RsaPublicKey> {
+        // The fingerprint is the lower 64 bits of the SHA1 of `rsa_public_key n:string e:string`
+        let mut buffer = Vec::new();
+        {
+            let mut serializer = Serializer::new(&mut buffer);
+            ByteBuf::from(n.to_vec()).serialize(&mut serializer)?;
+            ByteBuf::from(e.to_vec()).serialize(&mut serializer)?;
+        }
+
+        let hash = sha1(&[&buffer]);
+        let fingerprint = LittleEndian::read_i64(&hash[12..]);
+
+        Ok(RsaPublicKey {
+            n: BigUint::from_bytes_be(n),
+            e: BigUint::from_bytes_be(e),
+            fingerprint: fingerprint,
+        })
+    }
+
+    pub fn fingerprint(&self) -> i64 {
+        self.fingerprint
+    }
+
+    /// Encrypt the data as `sha1(data) + data + random padding` (255 bytes)
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() > DATA_WITH_HASH_LEN - 20 {
+            return Err(ErrorKind::InvalidMessageLength(DATA_WITH_HASH_LEN - 20, data.len()).into());
+        }
+
+        let mut data_with_hash = vec![0; DATA_WITH_HASH_LEN];
+
+        data_with_hash[..20].copy_from_slice(&sha1(&[data]));
+        data_with_hash[20..20 + data.len()].copy_from_slice(data);
+        rand::thread_rng().fill_bytes(&mut data_with_hash[20 + data.len()..]);
+
+        let encrypted = BigUint::from_bytes_be(&data_with_hash).modpow(&self.e, &self.n).to_bytes_be();
+
+        // The result is always as long as the modulus
+        let len = (self.n.bits() + 7) / 8;
+        let mut result = vec![0; len.saturating_sub(encrypted.len())];
+        result.extend(encrypted);
+
+        Ok(result)
+    }
+}
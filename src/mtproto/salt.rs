@@ -0,0 +1,368 @@
+use errors::*;
+use super::encrypted::EncryptedSession;
+use super::msg_id::Clock;
+use super::rpc::PendingCalls;
+use super::schema;
+
+/// Number of future salts asked for at once (the server answers with at most 64)
+const FUTURE_SALTS: i32 = 32;
+
+/// Seconds before the last known salt expires to ask for more
+const PREFETCH_MARGIN: i32 = 60 * 60;
+
+/// Seconds to wait on an answer to `get_future_salts` before asking again
+const REQUEST_TIMEOUT: i32 = 60;
+
+/// Error code of `bad_msg_notification` for an incorrect server salt
+const INCORRECT_SERVER_SALT: i32 = 48;
+
+/// Keeper of the server salt of a session.
+///
+/// Salts to use next are fetched ahead of time (`get_future_salts`) and switched to as they
+/// become valid; a message rejected for its salt (`bad_server_salt`) is resent with the salt
+/// the server asked for.
+///
+/// ```rust,ignore
+/// // Now and then (before sending)
+/// if let Some((_, message)) = salts.update(&mut session)? {
+///     transport.send(&message)?;
+/// }
+///
+/// // On `bad_server_salt`
+/// if let Some((_, message)) = salts.handle_bad_server_salt(&mut session, &mut pending, &notification)? {
+///     transport.send(&message)?;
+/// }
+///
+/// // On `future_salts`
+/// salts.handle_future_salts(future_salts);
+/// ```
+// https://core.telegram.org/mtproto/service_messages#request-for-several-future-salts
+#[derive(Debug, Default)]
+pub struct SaltManager {
+    /// Salts to use now and next by the time they become valid (`valid_since`)
+    salts: Vec<schema::FutureSalt>,
+
+    /// Identifier and server time of the outstanding `get_future_salts`
+    request: Option<(i64, i32)>,
+}
+
+impl SaltManager {
+    pub fn new() -> SaltManager {
+        Default::default()
+    }
+
+    /// Switch to the salt valid now and ask for more salts (`get_future_salts`) if the known
+    /// ones are about to run out; the message to send for it is returned along with its
+    /// identifier.
+    pub fn update<C: Clock>(&mut self,
+                            session: &mut EncryptedSession<C>)
+                            -> Result<Option<(i64, Vec<u8>)>> {
+        let now = session.message_id().server_time();
+
+        self.salts.retain(|salt| salt.valid_until > now);
+
+        let salt = self.salts.iter().find(|salt| salt.valid_since <= now).map(|salt| salt.salt);
+        if let Some(salt) = salt {
+            if salt != session.salt() {
+                debug!("switching to server salt {:016x}", salt);
+                session.set_salt(salt);
+            }
+        }
+
+        let valid_until = self.salts.iter().map(|salt| salt.valid_until).max().unwrap_or(now);
+        if valid_until - now > PREFETCH_MARGIN {
+            return Ok(None);
+        }
+
+        if let Some((_, sent)) = self.request {
+            if now - sent < REQUEST_TIMEOUT {
+                return Ok(None);
+            }
+        }
+
+        // NOTE: The answer (`future_salts`) is not wrapped in `rpc_result`
+        let (message_id, message) = session.seal(&schema::GetFutureSalts { num: FUTURE_SALTS }, true)?;
+        self.request = Some((message_id, now));
+
+        Ok(Some((message_id, message)))
+    }
+
+    /// Keep the salts answered to `get_future_salts`; `update` switches to them as they
+    /// become valid
+    pub fn handle_future_salts(&mut self, future_salts: schema::FutureSalts) {
+        if self.request.map(|(message_id, _)| message_id) == Some(future_salts.req_msg_id) {
+            self.request = None;
+        }
+
+        for salt in future_salts.salts {
+            if !self.salts.iter().any(|known| known.salt == salt.salt) {
+                self.salts.push(salt);
+            }
+        }
+
+        self.salts.sort_by_key(|salt| salt.valid_since);
+    }
+
+    /// Switch to the salt the server asked for and resend the rejected message; the message
+    /// to send is returned along with its new identifier. The pending calls in it (or in the
+    /// messages of a rejected container) wait on their new identifiers.
+    ///
+    /// A `bad_msg_notification` for an incorrect salt (error code 48) carries no salt; another
+    /// known salt valid now is switched to if there is one, otherwise the server answers the
+    /// resent message with the salt to use (`bad_server_salt`). There is nothing to resend for
+    /// any other error or if the rejected message was sent too long ago.
+    pub fn handle_bad_server_salt<C: Clock>(&mut self,
+                                            session: &mut EncryptedSession<C>,
+                                            pending: &mut PendingCalls,
+                                            notification: &schema::BadMsgNotification)
+                                            -> Result<Option<(i64, Vec<u8>)>> {
+        let bad_msg_id = match *notification {
+            schema::BadMsgNotification::bad_server_salt { bad_msg_id, new_server_salt, .. } => {
+                debug!("message {} rejected for its salt; switching to server salt {:016x}",
+                       bad_msg_id,
+                       new_server_salt);
+
+                // The known salts disagree with the server; they are asked for again
+                self.salts.clear();
+                session.set_salt(new_server_salt);
+
+                bad_msg_id
+            }
+
+            schema::BadMsgNotification::bad_msg_notification { bad_msg_id,
+                                                               error_code: INCORRECT_SERVER_SALT,
+                                                               .. } => {
+                let salt = session.salt();
+                self.salts.retain(|known| known.salt != salt);
+
+                let now = session.message_id().server_time();
+                let next = self.salts
+                    .iter()
+                    .find(|known| known.valid_since <= now && known.valid_until > now)
+                    .map(|known| known.salt);
+
+                match next {
+                    Some(next) => {
+                        debug!("message {} rejected for its salt; switching to server salt {:016x}",
+                               bad_msg_id,
+                               next);
+                        session.set_salt(next);
+                    }
+
+                    None => debug!("message {} rejected for its salt; no other salt is known", bad_msg_id),
+                }
+
+                bad_msg_id
+            }
+
+            _ => return Ok(None),
+        };
+
+        let resent = match session.resend(bad_msg_id)? {
+            Some(resent) => resent,
+            None => return Ok(None),
+        };
+
+        pending.rekey(bad_msg_id, resent.message_id);
+        for &(msg_id, new_msg_id) in &resent.renumbered {
+            pending.rekey(msg_id, new_msg_id);
+        }
+
+        Ok(Some((resent.message_id, resent.message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use mtproto::auth::AuthKey;
+    use mtproto::encrypted::EncryptedSession;
+    use mtproto::msg_id::{Clock, MessageIdGenerator};
+    use mtproto::rpc::PendingCalls;
+    use mtproto::schema;
+    use super::{SaltManager, PREFETCH_MARGIN, REQUEST_TIMEOUT};
+
+    /// A clock standing still at the seconds it is set to
+    #[derive(Clone, Default)]
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            Duration::from_secs(self.0.get())
+        }
+    }
+
+    fn session(clock: &FakeClock) -> EncryptedSession<FakeClock> {
+        EncryptedSession::with_message_id(AuthKey::new([7; 256]),
+                                          1,
+                                          MessageIdGenerator::with_clock(clock.clone()))
+    }
+
+    fn future_salt(valid_since: i32, valid_until: i32, salt: i64) -> schema::FutureSalt {
+        schema::FutureSalt {
+            valid_since: valid_since,
+            valid_until: valid_until,
+            salt: salt,
+        }
+    }
+
+    fn bad_server_salt(bad_msg_id: i64, new_server_salt: i64) -> schema::BadMsgNotification {
+        schema::BadMsgNotification::bad_server_salt {
+            bad_msg_id: bad_msg_id,
+            bad_msg_seqno: 1,
+            error_code: 48,
+            new_server_salt: new_server_salt,
+        }
+    }
+
+    fn bad_msg_notification(bad_msg_id: i64, error_code: i32) -> schema::BadMsgNotification {
+        schema::BadMsgNotification::bad_msg_notification {
+            bad_msg_id: bad_msg_id,
+            bad_msg_seqno: 1,
+            error_code: error_code,
+        }
+    }
+
+    #[test]
+    fn rotate_salts() {
+        let clock = FakeClock::default();
+        clock.0.set(1000);
+
+        let mut session = session(&clock);
+        let mut salts = SaltManager::new();
+
+        // No salt is known; more are asked for once until the request times out
+        let (request_id, _) = salts.update(&mut session).unwrap().unwrap();
+        assert!(salts.update(&mut session).unwrap().is_none());
+
+        salts.handle_future_salts(schema::FutureSalts {
+            req_msg_id: request_id,
+            now: 1000,
+            salts: vec![future_salt(2000, 20000, 3), future_salt(900, 2100, 2)],
+        });
+
+        assert!(salts.update(&mut session).unwrap().is_none());
+        assert_eq!(session.salt(), 2);
+
+        // Both are valid; the current one is kept until it expires
+        clock.0.set(2050);
+        assert!(salts.update(&mut session).unwrap().is_none());
+        assert_eq!(session.salt(), 2);
+
+        clock.0.set(2100);
+        assert!(salts.update(&mut session).unwrap().is_none());
+        assert_eq!(session.salt(), 3);
+
+        // The last salt is about to expire
+        clock.0.set(20000 - PREFETCH_MARGIN as u64);
+        assert!(salts.update(&mut session).unwrap().is_some());
+
+        clock.0.set(20000 - PREFETCH_MARGIN as u64 + REQUEST_TIMEOUT as u64 - 1);
+        assert!(salts.update(&mut session).unwrap().is_none());
+
+        clock.0.set(20000 - PREFETCH_MARGIN as u64 + REQUEST_TIMEOUT as u64);
+        assert!(salts.update(&mut session).unwrap().is_some());
+        assert_eq!(session.salt(), 3);
+    }
+
+    #[test]
+    fn resend_after_bad_server_salt() {
+        let clock = FakeClock::default();
+        clock.0.set(1000);
+
+        let mut session = session(&clock);
+        let mut pending = PendingCalls::new();
+        let mut salts = SaltManager::new();
+
+        let (message_id, _) = pending.call(&mut session, &schema::Ping { ping_id: 1 }, |_| {}).unwrap();
+
+        let (new_message_id, _) = salts.handle_bad_server_salt(&mut session,
+                                    &mut pending,
+                                    &bad_server_salt(message_id, 9))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.salt(), 9);
+        assert!(new_message_id > message_id);
+        assert!(!pending.contains(message_id));
+        assert!(pending.contains(new_message_id));
+
+        // The rejected message is resent once
+        assert!(salts.handle_bad_server_salt(&mut session, &mut pending, &bad_server_salt(message_id, 9))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn resend_container_after_bad_server_salt() {
+        let clock = FakeClock::default();
+        clock.0.set(1000);
+
+        let mut session = session(&clock);
+        let mut pending = PendingCalls::new();
+        let mut salts = SaltManager::new();
+
+        let first = session.pack(&schema::Ping { ping_id: 1 }, true).unwrap();
+        let second = session.pack(&schema::GetFutureSalts { num: 1 }, true).unwrap();
+        let ids = [first.msg_id, second.msg_id];
+        pending.insert::<schema::Ping, _>(first.msg_id, |_| {});
+        pending.insert::<schema::GetFutureSalts, _>(second.msg_id, |_| {});
+
+        let (container_id, _) = session.seal_container(vec![first, second]).unwrap();
+
+        let (new_container_id, _) = salts.handle_bad_server_salt(&mut session,
+                                    &mut pending,
+                                    &bad_server_salt(container_id, 9))
+            .unwrap()
+            .unwrap();
+
+        // The clock stands still; identifiers follow each other by 4: the inner messages
+        // are numbered again before the container
+        assert_eq!(new_container_id, container_id + 12);
+        assert_eq!(pending.len(), 2);
+        assert!(!pending.contains(ids[0]) && !pending.contains(ids[1]));
+        assert!(pending.contains(container_id + 4) && pending.contains(container_id + 8));
+    }
+
+    #[test]
+    fn resend_after_incorrect_salt() {
+        let clock = FakeClock::default();
+        clock.0.set(1000);
+
+        let mut session = session(&clock);
+        let mut pending = PendingCalls::new();
+        let mut salts = SaltManager::new();
+
+        salts.handle_future_salts(schema::FutureSalts {
+            req_msg_id: 0,
+            now: 1000,
+            salts: vec![future_salt(0, 10000, 5), future_salt(0, 10000, 6)],
+        });
+        assert!(salts.update(&mut session).unwrap().is_none());
+        assert_eq!(session.salt(), 5);
+
+        let (message_id, _) = pending.call(&mut session, &schema::Ping { ping_id: 1 }, |_| {}).unwrap();
+
+        // Another error is not about the salt
+        assert!(salts.handle_bad_server_salt(&mut session, &mut pending, &bad_msg_notification(message_id, 16))
+            .unwrap()
+            .is_none());
+        assert_eq!(session.salt(), 5);
+        assert!(pending.contains(message_id));
+
+        let (new_message_id, _) = salts.handle_bad_server_salt(&mut session,
+                                    &mut pending,
+                                    &bad_msg_notification(message_id, 48))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.salt(), 6);
+        assert!(pending.contains(new_message_id) && !pending.contains(message_id));
+
+        // The rejected salt is not switched back to
+        assert!(salts.update(&mut session).unwrap().is_none());
+        assert_eq!(session.salt(), 6);
+    }
+}
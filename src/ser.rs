@@ -1,22 +1,118 @@
 use serde::ser::{self, Serialize};
 use byteorder::{LittleEndian, WriteBytesExt};
 use errors::*;
+use tl;
 
 pub struct Serializer<W>
     where W: WriteBytesExt
 {
     writer: W,
+
+    // Presence of the conditional field (`flags.N?T`) being serialized
+    presence: Option<bool>,
+
+    // The value being serialized is of a bare type and written without its constructor ID
+    bare: bool,
+
+    // The elements of the vector being serialized are of a bare type (`vector<%Message>`)
+    bare_elements: bool,
 }
 
 impl<W> Serializer<W>
     where W: WriteBytesExt
 {
     pub fn new(writer: W) -> Serializer<W> {
-        Serializer { writer: writer }
+        Serializer {
+            writer: writer,
+            presence: None,
+            bare: false,
+            bare_elements: false,
+        }
     }
+
+    /// Write the constructor ID carried in the name of a boxed type (`predicate#id`)
+    fn serialize_constructor_id(&mut self, name: &'static str) -> Result<()> {
+        match tl::constructor_id(name) {
+            Some(id) => Ok(self.writer.write_u32::<LittleEndian>(id)?),
+            None => Err(ser::Error::custom(format!("no constructor ID for {}", name))),
+        }
+    }
+}
+
+pub struct Compound<'a, W: 'a>
+    where W: WriteBytesExt
+{
+    ser: &'a mut Serializer<W>,
+
+    // Fields that follow a flags word (`flags:#`) are buffered until all of the conditional
+    // fields, and so the flags, are known
+    flags: Vec<(&'static str, u32, Vec<u8>)>,
+
+    // The elements of the sequence are written bare
+    bare_elements: bool,
 }
 
-pub struct Compound<'a, W: 'a>(&'a mut Serializer<W>) where W: WriteBytesExt;
+impl<'a, W> Compound<'a, W>
+    where W: WriteBytesExt
+{
+    fn new(ser: &'a mut Serializer<W>) -> Self {
+        Compound {
+            ser: ser,
+            flags: Vec::new(),
+            bare_elements: false,
+        }
+    }
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        let field = tl::Field::parse(key);
+
+        for word in field.flags {
+            self.flags.push((word, 0, Vec::new()));
+        }
+
+        if self.flags.is_empty() {
+            self.ser.bare = field.bare;
+            self.ser.bare_elements = field.bare_elements;
+            return value.serialize(&mut *self.ser);
+        }
+
+        let present = {
+            let mut ser = Serializer::new(&mut self.flags.last_mut().unwrap().2);
+            if field.condition.is_some() {
+                ser.presence = Some(false);
+            }
+
+            ser.bare = field.bare;
+            ser.bare_elements = field.bare_elements;
+
+            value.serialize(&mut ser)?;
+            ser.presence
+        };
+
+        if let Some((word, bit)) = field.condition {
+            let flags = match self.flags.iter_mut().find(|flags| flags.0 == word) {
+                Some(flags) => flags,
+                None => return Err(ser::Error::custom(format!("no flags word `{}` for {}", word, key))),
+            };
+
+            if present == Some(true) {
+                flags.1 |= 1 << bit;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        // Write out the flags followed by the fields buffered after them
+        for (_, flags, buffer) in self.flags {
+            self.ser.writer.write_u32::<LittleEndian>(flags)?;
+            self.ser.writer.write_all(&buffer)?;
+        }
+
+        Ok(())
+    }
+}
 
 impl<'a, W> ser::Serializer for &'a mut Serializer<W>
     where W: WriteBytesExt
@@ -34,8 +130,15 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
     #[inline]
     fn serialize_bool(self, value: bool) -> Result<()> {
-        // NOTE: Telegram has no representation for this.
-        Err(ser::Error::custom("Telegram does not support Serializer::serialize_bool"))
+        if let Some(ref mut present) = self.presence {
+            // A conditional field of type `true` (`flags.N?true`) is only the bit in the flags
+            *present = value;
+            return Ok(());
+        }
+
+        // Bool is the boxed boolTrue or boolFalse
+        let id = if value { tl::BOOL_TRUE } else { tl::BOOL_FALSE };
+        Ok(self.writer.write_u32::<LittleEndian>(id)?)
     }
 
     #[inline]
@@ -96,9 +199,15 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
     #[inline]
     fn serialize_str(self, value: &str) -> Result<()> {
+        // A string is serialized as its UTF-8 bytes
+        ser::Serializer::serialize_bytes(self, value.as_bytes())
+    }
+
+    #[inline]
+    fn serialize_bytes(self, value: &[u8]) -> Result<()> {
         let len = value.len();
 
-        if len <= 253 {
+        let header_len = if len <= 253 {
             // If L <= 253, the serialization contains one byte with the value of L,
             // then L bytes of the string followed by 0 to 3 characters containing 0,
             // such that the overall length of the value be divisible by 4,
@@ -106,6 +215,7 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
             // of int(L/4)+1 32-bit little-endian integers.
 
             self.writer.write_u8(len as u8)?;
+            1
         } else {
             // If L >= 254, the serialization contains byte 254, followed by 3
             // bytes with the string length L in little-endian order, followed by L
@@ -113,13 +223,15 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
             self.writer.write_u8(254)?;
             self.writer.write_uint::<LittleEndian>(len as u64, 3)?;
-        }
+            4
+        };
 
-        for byte in value.as_bytes() {
+        for byte in value {
             self.writer.write_u8(*byte)?;
         }
 
-        let rem = len % 4;
+        // NOTE: The length prefix counts towards the padding
+        let rem = (header_len + len) % 4;
         if rem > 0 {
             for _ in 0..(4 - rem) {
                 self.writer.write_u8(0)?;
@@ -129,21 +241,29 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
         Ok(())
     }
 
-    #[inline]
-    fn serialize_bytes(self, value: &[u8]) -> Result<()> {
-        // NOTE: Telegram has no representation for this.
-        Err(ser::Error::custom("Telegram does not support Serializer::serialize_bytes"))
-    }
-
     #[inline]
     fn serialize_none(self) -> Result<()> {
-        // NOTE: Telegram has no representation for this.
+        if self.presence.is_some() {
+            // An absent conditional field (`flags.N?T`) is not written; its bit is unset
+            return Ok(());
+        }
+
+        // NOTE: Telegram has no representation for this outside of a conditional field.
         Err(ser::Error::custom("Telegram does not support Serializer::serialize_none"))
     }
 
     #[inline]
     fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
-        // NOTE: Telegram has no representation for this.
+        if self.presence.is_some() {
+            // A present conditional field (`flags.N?T`) is written as its value
+            self.presence = None;
+            value.serialize(&mut *self)?;
+            self.presence = Some(true);
+
+            return Ok(());
+        }
+
+        // NOTE: Telegram has no representation for this outside of a conditional field.
         Err(ser::Error::custom("Telegram does not support Serializer::serialize_some"))
     }
 
@@ -155,8 +275,13 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
     #[inline]
     fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
-        // NOTE: Telegram has no representation for this.
-        unimplemented!();
+        // A constructor without parameters is only its constructor ID; nothing at all if bare
+        if self.bare {
+            self.bare = false;
+            return Ok(());
+        }
+
+        self.serialize_constructor_id(name)
     }
 
     #[inline]
@@ -165,8 +290,8 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                               variant_index: usize,
                               variant: &'static str)
                               -> Result<()> {
-        // NOTE: Telegram has no representation for this.
-        unimplemented!();
+        // A constructor without parameters is only its constructor ID
+        self.serialize_constructor_id(variant)
     }
 
     #[inline]
@@ -174,8 +299,8 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                                                        name: &'static str,
                                                        value: &T)
                                                        -> Result<()> {
-        // NOTE: Telegram has no representation for this.
-        unimplemented!();
+        // A newtype is transparent; it is written as the value it wraps
+        value.serialize(self)
     }
 
     #[inline]
@@ -191,17 +316,38 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 
     #[inline]
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        return Ok(Compound(self));
+        let len = match len {
+            Some(len) if len <= i32::max_value() as usize => len as i32,
+            Some(len) => return Err(ser::Error::custom(format!("vector too long ({})", len))),
+            None => return Err(ser::Error::custom("vector length must be known")),
+        };
+
+        // A boxed Vector starts with its constructor ID; a bare vector with the length
+        if !self.bare {
+            self.writer.write_u32::<LittleEndian>(tl::VECTOR)?;
+        }
+
+        self.bare = false;
+        self.writer.write_i32::<LittleEndian>(len)?;
+
+        let bare_elements = self.bare_elements;
+        self.bare_elements = false;
+
+        let mut compound = Compound::new(self);
+        compound.bare_elements = bare_elements;
+
+        return Ok(compound);
     }
 
     #[inline]
     fn serialize_seq_fixed_size(self, len: usize) -> Result<Self::SerializeSeq> {
-        return Ok(Compound(self));
+        // A fixed size sequence is written as its elements; like a tuple
+        return Ok(Compound::new(self));
     }
 
     #[inline]
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        return Ok(Compound(self));
+        return Ok(Compound::new(self));
     }
 
     #[inline]
@@ -209,7 +355,7 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                               name: &'static str,
                               len: usize)
                               -> Result<Self::SerializeTupleStruct> {
-        return Ok(Compound(self));
+        return Ok(Compound::new(self));
     }
 
     #[inline]
@@ -219,17 +365,24 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                                variant: &'static str,
                                len: usize)
                                -> Result<Self::SerializeTupleVariant> {
-        return Ok(Compound(self));
+        return Ok(Compound::new(self));
     }
 
     #[inline]
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        return Ok(Compound(self));
+        return Ok(Compound::new(self));
     }
 
     #[inline]
     fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        return Ok(Compound(self));
+        // A struct without a constructor ID, or of a bare type (`%Message`), is serialized bare
+        if !self.bare && tl::constructor_id(name).is_some() {
+            self.serialize_constructor_id(name)?;
+        }
+
+        self.bare = false;
+
+        return Ok(Compound::new(self));
     }
 
     #[inline]
@@ -239,7 +392,9 @@ impl<'a, W> ser::Serializer for &'a mut Serializer<W>
                                 variant: &'static str,
                                 len: usize)
                                 -> Result<Self::SerializeStructVariant> {
-        return Ok(Compound(self));
+        self.serialize_constructor_id(variant)?;
+
+        return Ok(Compound::new(self));
     }
 }
 
@@ -251,12 +406,18 @@ impl<'a, W> ser::SerializeSeq for Compound<'a, W>
 
     #[inline]
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        unimplemented!();
+        self.ser.bare = self.bare_elements;
+
+        let result = value.serialize(&mut *self.ser);
+        self.ser.bare = false;
+
+        result
     }
 
     #[inline]
     fn end(self) -> Result<()> {
-        unimplemented!();
+        // Do nothing; there is no state
+        Ok(())
     }
 }
 
@@ -290,7 +451,7 @@ impl<'a, W> ser::SerializeTuple for Compound<'a, W>
 
     #[inline]
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut *self.0)
+        value.serialize(&mut *self.ser)
     }
 
     #[inline]
@@ -341,14 +502,13 @@ impl<'a, W> ser::SerializeStruct for Compound<'a, W>
     type Error = Error;
 
     #[inline]
-    fn serialize_field<T: ?Sized + Serialize>(&mut self, _: &'static str, value: &T) -> Result<()> {
-        value.serialize(&mut *self.0)
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        Compound::serialize_field(self, key, value)
     }
 
     #[inline]
     fn end(self) -> Result<()> {
-        // Do nothing; there is no state
-        Ok(())
+        Compound::end(self)
     }
 }
 
@@ -363,11 +523,232 @@ impl<'a, W> ser::SerializeStructVariant for Compound<'a, W>
                                               key: &'static str,
                                               value: &T)
                                               -> Result<()> {
-        unimplemented!();
+        Compound::serialize_field(self, key, value)
     }
 
     #[inline]
     fn end(self) -> Result<()> {
-        unimplemented!();
+        Compound::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use serde::bytes::{ByteBuf, Bytes};
+    use errors::*;
+    use tl::{Identifiable, Object};
+    use mtproto::schema;
+    use super::Serializer;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "inner#11223344")]
+    struct Inner {
+        value: i32,
+    }
+
+    // flagged#aabbccdd flags:# silent:flags.0?true title:flags.1?string pinned:flags.2?Bool
+    //                  inner:flags.3?Inner id:long = Flagged
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "flagged#aabbccdd")]
+    struct Flagged {
+        #[serde(rename = "flags:# silent:flags.0?true")]
+        silent: bool,
+
+        #[serde(rename = "title:flags.1?string")]
+        title: Option<String>,
+
+        #[serde(rename = "pinned:flags.2?Bool")]
+        pinned: Option<bool>,
+
+        #[serde(rename = "inner:flags.3?Inner")]
+        inner: Option<Inner>,
+
+        id: i64,
+    }
+
+    // bare#55667788 values:vector<long> inners:vector<%Inner> = Bare
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename = "bare#55667788")]
+    struct Bare {
+        #[serde(rename = "values:vector<long>")]
+        values: Vec<i64>,
+
+        #[serde(rename = "inners:vector<%Inner>")]
+        inners: Vec<Inner>,
+    }
+
+    fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        value.serialize(&mut Serializer::new(&mut buffer)).unwrap();
+
+        buffer
+    }
+
+    #[test]
+    fn serialize_all_flags() {
+        let flagged = Flagged {
+            silent: true,
+            title: Some("abc".to_string()),
+            pinned: Some(false),
+            inner: Some(Inner { value: 7 }),
+            id: 0x0102030405060708,
+        };
+
+        let buffer = serialize(&flagged);
+        assert_eq!(buffer,
+                   [0xdd, 0xcc, 0xbb, 0xaa, 0x0f, 0x00, 0x00, 0x00, 0x03, 0x61, 0x62,
+                    0x63, 0x37, 0x97, 0x79, 0xbc, 0x44, 0x33, 0x22, 0x11, 0x07, 0x00,
+                    0x00, 0x00, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+
+        assert_eq!(Object(buffer).decode::<Flagged>().unwrap(), flagged);
+    }
+
+    #[test]
+    fn serialize_no_flags() {
+        let flagged = Flagged {
+            silent: false,
+            title: None,
+            pinned: None,
+            inner: None,
+            id: 0x0102030405060708,
+        };
+
+        // The flags word comes first and the required field right after it
+        let buffer = serialize(&flagged);
+        assert_eq!(buffer,
+                   [0xdd, 0xcc, 0xbb, 0xaa, 0x00, 0x00, 0x00, 0x00, 0x08, 0x07, 0x06,
+                    0x05, 0x04, 0x03, 0x02, 0x01]);
+
+        assert_eq!(Object(buffer).decode::<Flagged>().unwrap(), flagged);
+    }
+
+    #[test]
+    fn serialize_true_flag() {
+        let flagged = Flagged {
+            silent: true,
+            title: None,
+            pinned: Some(true),
+            inner: None,
+            id: 1,
+        };
+
+        // A `true` field is only its bit; it takes up no bytes
+        let buffer = serialize(&flagged);
+        assert_eq!(buffer,
+                   [0xdd, 0xcc, 0xbb, 0xaa, 0x05, 0x00, 0x00, 0x00, 0xb5, 0x75, 0x72,
+                    0x99, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        assert_eq!(Object(buffer).decode::<Flagged>().unwrap(), flagged);
+    }
+
+    #[test]
+    fn write_constructor_id() {
+        let ping = schema::Ping { ping_id: 0x0102030405060708 };
+        assert_eq!(ping.constructor_id(), 0x7abe77ec);
+
+        let buffer = serialize(&ping);
+        assert_eq!(buffer,
+                   [0xec, 0x77, 0xbe, 0x7a, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02,
+                    0x01]);
+
+        assert_eq!(Object(buffer).decode::<schema::Ping>().unwrap().ping_id, 0x0102030405060708);
+    }
+
+    #[test]
+    fn check_constructor_id() {
+        // A pong is not a ping
+        let buffer = [0xc5, 0x73, 0x77, 0x34, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01];
+
+        match Object(buffer.to_vec()).decode::<schema::Ping>() {
+            Err(Error(ErrorKind::UnknownConstructor(_, 0x347773c5), _)) => {}
+            result => panic!("expected UnknownConstructor; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn serialize_bool() {
+        assert_eq!(serialize(&true), [0xb5, 0x75, 0x72, 0x99]);
+        assert_eq!(serialize(&false), [0x37, 0x97, 0x79, 0xbc]);
+
+        assert_eq!(Object(vec![0xb5, 0x75, 0x72, 0x99]).decode::<bool>().unwrap(), true);
+        assert_eq!(Object(vec![0x37, 0x97, 0x79, 0xbc]).decode::<bool>().unwrap(), false);
+
+        match Object(vec![0x15, 0xc4, 0xb5, 0x1c]).decode::<bool>() {
+            Err(Error(ErrorKind::UnknownConstructor("Bool", 0x1cb5c415), _)) => {}
+            result => panic!("expected UnknownConstructor; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn serialize_bytes() {
+        // Length, header and padding
+        let cases = [(0, vec![0x00], 3), (1, vec![0x01], 2), (3, vec![0x03], 0),
+                     (253, vec![0xfd], 2), (254, vec![0xfe, 0xfe, 0x00, 0x00], 2),
+                     (300, vec![0xfe, 0x2c, 0x01, 0x00], 0)];
+
+        for &(len, ref header, padding_len) in &cases {
+            let value = vec![0x61; len];
+            let buffer = serialize(&Bytes::new(&value));
+
+            assert_eq!(buffer.len(), header.len() + len + padding_len);
+            assert_eq!(&buffer[..header.len()], &header[..]);
+            assert_eq!(&buffer[header.len()..header.len() + len], &value[..]);
+            assert!(buffer[header.len() + len..].iter().all(|&byte| byte == 0));
+
+            // A string is written the same
+            assert_eq!(serialize(&"a".repeat(len)), buffer);
+
+            let decoded = Object(buffer).decode::<ByteBuf>().unwrap();
+            assert_eq!(&decoded[..], &value[..]);
+        }
+    }
+
+    #[test]
+    fn serialize_boxed_vector() {
+        let values = vec![1i64, 2];
+        let buffer = serialize(&values);
+        assert_eq!(buffer,
+                   [0x15, 0xc4, 0xb5, 0x1c, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00]);
+        assert_eq!(Object(buffer).decode::<Vec<i64>>().unwrap(), values);
+
+        // Boxed elements carry their constructor IDs
+        let inners = vec![Inner { value: 7 }];
+        let buffer = serialize(&inners);
+        assert_eq!(buffer,
+                   [0x15, 0xc4, 0xb5, 0x1c, 0x01, 0x00, 0x00, 0x00, 0x44, 0x33, 0x22,
+                    0x11, 0x07, 0x00, 0x00, 0x00]);
+        assert_eq!(Object(buffer).decode::<Vec<Inner>>().unwrap(), inners);
+
+        let empty = Vec::<i64>::new();
+        assert_eq!(serialize(&empty), [0x15, 0xc4, 0xb5, 0x1c, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn serialize_bare_vector() {
+        let bare = Bare {
+            values: vec![1],
+            inners: vec![Inner { value: 7 }, Inner { value: 8 }],
+        };
+
+        // Only the counts; the elements of `vector<%Inner>` without their constructor IDs
+        let buffer = serialize(&bare);
+        assert_eq!(buffer,
+                   [0x88, 0x77, 0x66, 0x55, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x07, 0x00,
+                    0x00, 0x00, 0x08, 0x00, 0x00, 0x00]);
+        assert_eq!(Object(buffer).decode::<Bare>().unwrap(), bare);
+    }
+
+    #[test]
+    fn reject_vector_constructor() {
+        let buffer = vec![0x14, 0xc4, 0xb5, 0x1c, 0x00, 0x00, 0x00, 0x00];
+
+        match Object(buffer).decode::<Vec<i64>>() {
+            Err(Error(ErrorKind::UnknownConstructor(_, 0x1cb5c414), _)) => {}
+            result => panic!("expected UnknownConstructor; found {:?}", result),
+        }
     }
 }
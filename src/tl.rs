@@ -0,0 +1,317 @@
+use std::fmt;
+use byteorder::{ByteOrder, LittleEndian};
+use serde::{de, ser, Serialize, Deserialize};
+use errors::*;
+use ser::Serializer;
+use de::Deserializer;
+
+/// Constructor ID of `boolTrue = Bool`
+pub const BOOL_TRUE: u32 = 0x997275b5;
+
+/// Constructor ID of `boolFalse = Bool`
+pub const BOOL_FALSE: u32 = 0xbc799737;
+
+/// Constructor ID of `vector#1cb5c415 {t:Type} # [ t ] = Vector t`
+pub const VECTOR: u32 = 0x1cb5c415;
+
+/// TL `int128`; the bytes as they are written (little-endian)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Int128(pub [u8; 16]);
+
+/// TL `int256`; the bytes as they are written (little-endian)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Int256(pub [u8; 32]);
+
+impl From<i128> for Int128 {
+    fn from(value: i128) -> Self {
+        let mut bytes = [0; 16];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = (value >> (index * 8)) as u8;
+        }
+
+        Int128(bytes)
+    }
+}
+
+impl From<Int128> for i128 {
+    fn from(value: Int128) -> Self {
+        value.0.iter().rev().fold(0, |result, byte| (result << 8) | (*byte as i128))
+    }
+}
+
+/// Constructor ID of `gzip_packed#3072cfa1 packed_data:bytes = Object`
+pub const GZIP_PACKED: u32 = 0x3072cfa1;
+
+/// Name `Object` is serialized with; the deserializer reads the rest of the input as its bytes
+pub const OBJECT: &'static str = "Object";
+
+/// A boxed object of any type (TL `Object`), kept serialized; its constructor ID tells
+/// what to decode it as.
+///
+/// NOTE: An `Object` takes up the rest of the input it is deserialized from (`rpc_result`)
+/// unless its length is given by the field before it (`body:Object[bytes]` in `message`).
+/// An object packed with `gzip_packed` is kept inflated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object(pub Vec<u8>);
+
+impl Object {
+    /// Serialize a boxed value as an `Object`
+    pub fn encode<T: Serialize>(value: &T) -> Result<Object> {
+        let mut buffer = Vec::new();
+        value.serialize(&mut Serializer::new(&mut buffer))?;
+
+        Ok(Object(buffer))
+    }
+
+    /// Constructor ID the object starts with
+    pub fn constructor_id(&self) -> Option<u32> {
+        if self.0.len() < 4 {
+            return None;
+        }
+
+        Some(LittleEndian::read_u32(&self.0))
+    }
+
+    /// Deserialize the object as a `T`; all of it must be read
+    pub fn decode<T: Deserialize>(&self) -> Result<T> {
+        let mut buffer = &self.0[..];
+        let value = T::deserialize(&mut Deserializer::new(&mut buffer))?;
+        if !buffer.is_empty() {
+            return Err(ErrorKind::InvalidMessageLength(self.0.len(), self.0.len() - buffer.len())
+                .into());
+        }
+
+        Ok(value)
+    }
+}
+
+impl Serialize for Object {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        // The bytes are written as they are; without a length
+        let mut seq = serializer.serialize_seq_fixed_size(self.0.len())?;
+        for byte in &self.0 {
+            ser::SerializeSeq::serialize_element(&mut seq, byte)?;
+        }
+
+        ser::SerializeSeq::end(seq)
+    }
+}
+
+impl Deserialize for Object {
+    fn deserialize<D: de::Deserializer>(deserializer: D) -> ::std::result::Result<Object, D::Error> {
+        struct ObjectVisitor;
+
+        impl de::Visitor for ObjectVisitor {
+            type Value = Object;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a serialized object")
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> ::std::result::Result<Object, E> {
+                Ok(Object(value))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(OBJECT, ObjectVisitor)
+    }
+}
+
+/// A TL type that can tell which of its constructors a value was built with.
+///
+/// Implemented by the generated schema for every type.
+pub trait Identifiable {
+    /// Constructor ID of this value as written before a boxed TL object
+    fn constructor_id(&self) -> u32;
+}
+
+/// A TL function; the request is the serialized value itself and the server answers
+/// it with a `Return`.
+///
+/// Implemented by the generated schema for every method.
+pub trait RemoteCall: Serialize {
+    /// Type of the result the server answers this call with
+    type Return: Deserialize;
+}
+
+/// Extract the constructor ID from a serialized name of the form `predicate#id`
+/// as output by `telegram_codegen`.
+pub fn constructor_id(name: &str) -> Option<u32> {
+    let mut s = name.rsplitn(2, '#');
+    match (s.next(), s.next()) {
+        (Some(id), Some(_)) => u32::from_str_radix(id, 16).ok(),
+        _ => None,
+    }
+}
+
+/// A struct field as named by `telegram_codegen`
+///
+/// Fields that need more than a name to be serialized are named by their TL declaration,
+/// preceded by the declarations of the flags words before them (`flags:# title:flags.3?string`).
+pub struct Field<'a> {
+    /// Name of the field (`title`)
+    pub name: &'a str,
+
+    /// Flags words (`flags:#`) declared right before the field
+    pub flags: Vec<&'a str>,
+
+    /// Flags word and bit the presence of the field is conditional on (`flags.3?string`)
+    pub condition: Option<(&'a str, u32)>,
+
+    /// The type of the field is bare (`vector<long>`, `%Message`) and written without its
+    /// constructor ID
+    pub bare: bool,
+
+    /// The elements of the field are of a bare type (`vector<%Message>`)
+    pub bare_elements: bool,
+
+    /// Field before this one that holds its length in bytes (`body:Object[bytes]` in
+    /// `message`); an `Object` otherwise takes up the rest of the input
+    pub length: Option<&'a str>,
+}
+
+impl<'a> Field<'a> {
+    pub fn parse(name: &'a str) -> Field<'a> {
+        let mut field = Field {
+            name: name,
+            flags: Vec::new(),
+            condition: None,
+            bare: false,
+            bare_elements: false,
+            length: None,
+        };
+
+        for declaration in name.split(' ') {
+            let mut s = declaration.splitn(2, ':');
+            let kind = match (s.next(), s.next()) {
+                (Some(name), Some("#")) => {
+                    field.flags.push(name);
+                    continue;
+                }
+
+                (Some(name), Some(kind)) => {
+                    field.name = name;
+                    kind
+                }
+
+                _ => continue,
+            };
+
+            // flags.3?string => (flags, 3)
+            let kind = match kind.find('?') {
+                Some(index) => {
+                    let mut s = kind[..index].splitn(2, '.');
+                    if let (Some(word), Some(bit)) = (s.next(), s.next()) {
+                        field.condition = bit.parse().ok().map(|bit| (word, bit));
+                    }
+
+                    &kind[index + 1..]
+                }
+
+                None => kind,
+            };
+
+            // Object[bytes] => (Object, bytes)
+            let kind = match (kind.find('['), kind.ends_with(']')) {
+                (Some(index), true) => {
+                    field.length = Some(&kind[index + 1..kind.len() - 1]);
+                    &kind[..index]
+                }
+
+                _ => kind,
+            };
+
+            field.bare = kind.starts_with("vector<") || kind.starts_with('%');
+            field.bare_elements = kind.starts_with("vector<%");
+        }
+
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schema;
+    use mtproto::schema as mtproto;
+    use super::{Identifiable, Int128, Int256, Object, RemoteCall};
+
+    /// Serialize a call and decode what the server answers it with
+    fn call<T: RemoteCall>(request: &T, answer: &[u8]) -> (Vec<u8>, T::Return) {
+        (Object::encode(request).unwrap().0, Object(answer.to_vec()).decode().unwrap())
+    }
+
+    #[test]
+    fn call_method() {
+        // future_salts#ae500895 req_msg_id:long now:int salts:vector<future_salt>
+        let answer = [0x95, 0x08, 0x50, 0xae, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                      0x00, 0xe8, 0x03, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xe8, 0x03,
+                      0x00, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00,
+                      0x00, 0x00, 0x00];
+
+        let (request, future_salts) = call(&mtproto::GetFutureSalts { num: 2 }, &answer);
+        assert_eq!(request, [0x04, 0xbd, 0x21, 0xb9, 0x02, 0x00, 0x00, 0x00]);
+
+        assert_eq!((future_salts.req_msg_id, future_salts.now), (8, 1000));
+        assert_eq!(future_salts.salts.len(), 1);
+        assert_eq!((future_salts.salts[0].valid_since,
+                    future_salts.salts[0].valid_until,
+                    future_salts.salts[0].salt),
+                   (1000, 2000, 5));
+    }
+
+    #[test]
+    fn call_namespaced_method() {
+        let request = schema::help::GetNearestDc;
+        assert_eq!(request.constructor_id(), 0x1fb33026);
+
+        // A method without parameters is only its ID
+        assert_eq!(Object::encode(&request).unwrap().0, [0x26, 0x30, 0xb3, 0x1f]);
+    }
+
+    #[test]
+    fn convert_int128() {
+        let value = Int128::from(0x0102030405060708090a0b0c0d0e0f10);
+        assert_eq!(value.0,
+                   [0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x08, 0x07, 0x06,
+                    0x05, 0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(i128::from(value), 0x0102030405060708090a0b0c0d0e0f10);
+
+        assert_eq!(Int128::from(-1).0, [0xff; 16]);
+        assert_eq!(i128::from(Int128::from(-2)), -2);
+    }
+
+    #[test]
+    fn serialize_int128_and_int256() {
+        // The bytes as they are; no length
+        let value = Int128::from(0x0102030405060708090a0b0c0d0e0f10);
+        assert_eq!(Object::encode(&value).unwrap().0, value.0);
+        assert_eq!(Object(value.0.to_vec()).decode::<Int128>().unwrap(), value);
+
+        let mut bytes = [0; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        assert_eq!(Object::encode(&Int256(bytes)).unwrap().0, &bytes[..]);
+        assert_eq!(Object(bytes.to_vec()).decode::<Int256>().unwrap(), Int256(bytes));
+    }
+
+    #[test]
+    fn decode_res_pq() {
+        // resPQ#05162463 nonce:int128 server_nonce:int128 pq:string
+        //                server_public_key_fingerprints:Vector<long>
+        let mut answer = vec![0x63, 0x24, 0x16, 0x05];
+        answer.extend_from_slice(&[1; 16]);
+        answer.extend_from_slice(&[2; 16]);
+        answer.extend_from_slice(&[0x08, 0x17, 0xed, 0x48, 0x94, 0x1a, 0x08, 0xf9, 0x81, 0x00,
+                                   0x00, 0x00]);
+        answer.extend_from_slice(&[0x15, 0xc4, 0xb5, 0x1c, 0x01, 0x00, 0x00, 0x00, 0x21, 0x6b,
+                                   0xe8, 0x6c, 0x02, 0x2b, 0xb4, 0xc3]);
+
+        let res_pq = Object(answer).decode::<mtproto::ResPQ>().unwrap();
+        assert_eq!(res_pq.nonce, Int128([1; 16]));
+        assert_eq!(res_pq.server_nonce, Int128([2; 16]));
+        assert_eq!(&res_pq.pq[..], &[0x17, 0xed, 0x48, 0x94, 0x1a, 0x08, 0xf9, 0x81]);
+        assert_eq!(res_pq.server_public_key_fingerprints, [0xc3b42b026ce86b21u64 as i64]);
+    }
+}
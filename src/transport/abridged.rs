@@ -0,0 +1,133 @@
+use std::io::{Read, Write};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use errors::*;
+use super::{Framing, Transport, check_len, check_packet};
+
+/// Tag sent at the start of the connection
+const TAG: u8 = 0xef;
+
+/// The abridged framing; the length in 4-byte words is one byte (`< 0x7f`) or `0x7f`
+/// followed by three bytes
+// https://core.telegram.org/mtproto/mtproto-transports#abridged
+pub struct Abridged<S> {
+    stream: S,
+    tag_sent: bool,
+}
+
+impl<S: Read + Write> Abridged<S> {
+    pub fn new(stream: S) -> Abridged<S> {
+        Abridged {
+            stream: stream,
+            tag_sent: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for Abridged<S> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        if packet.len() % 4 != 0 {
+            return Err(ErrorKind::InvalidPacketLength(packet.len()).into());
+        }
+
+        // The length in words must fit in three bytes
+        check_len(packet.len())?;
+
+        let mut buffer = Vec::with_capacity(packet.len() + 5);
+        if !self.tag_sent {
+            buffer.push(TAG);
+        }
+
+        let len = packet.len() / 4;
+        if len < 0x7f {
+            buffer.push(len as u8);
+        } else {
+            buffer.push(0x7f);
+            buffer.write_u24::<LittleEndian>(len as u32)?;
+        }
+
+        buffer.write_all(packet)?;
+
+        self.stream.write_all(&buffer)?;
+        self.stream.flush()?;
+        self.tag_sent = true;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let len = match self.stream.read_u8()? {
+            0x7f => self.stream.read_u24::<LittleEndian>()? as usize,
+            len => len as usize,
+        } * 4;
+
+        check_len(len)?;
+
+        let mut packet = vec![0; len];
+        self.stream.read_exact(&mut packet)?;
+        check_packet(&packet)?;
+
+        Ok(packet)
+    }
+}
+
+impl<S: Read + Write> Framing<S> for Abridged<S> {
+    fn obfuscated_tag() -> [u8; 4] {
+        [TAG; 4]
+    }
+
+    fn with_tag_sent(stream: S) -> Abridged<S> {
+        Abridged {
+            stream: stream,
+            tag_sent: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use errors::*;
+    use transport::{Transport, MAX_PACKET_LEN};
+    use super::Abridged;
+
+    #[test]
+    fn send_lengths() {
+        let mut connection = Abridged::new(Cursor::new(Vec::new()));
+        connection.send(&[1; 8]).unwrap();
+        connection.send(&[2; 0x7f * 4]).unwrap();
+
+        let sent = connection.into_inner().into_inner();
+        assert_eq!(sent[..10], [0xef, 0x02, 1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(sent[10..14], [0x7f, 0x7f, 0x00, 0x00]);
+        assert_eq!(sent.len(), 14 + 0x7f * 4);
+    }
+
+    #[test]
+    fn receive_lengths() {
+        let mut buffer = vec![0x02, 1, 1, 1, 1, 1, 1, 1, 1, 0x7f, 0x7f, 0x00, 0x00];
+        buffer.extend_from_slice(&[2; 0x7f * 4]);
+
+        let mut connection = Abridged::new(Cursor::new(buffer));
+        assert_eq!(connection.receive().unwrap(), [1; 8]);
+        assert_eq!(connection.receive().unwrap(), &[2; 0x7f * 4][..]);
+    }
+
+    #[test]
+    fn reject_send_len() {
+        let mut connection = Abridged::new(Cursor::new(Vec::new()));
+
+        for &len in &[6, MAX_PACKET_LEN + 4] {
+            match connection.send(&vec![0; len]) {
+                Err(Error(ErrorKind::InvalidPacketLength(found), _)) => assert_eq!(found, len),
+                result => panic!("expected InvalidPacketLength; found {:?}", result),
+            }
+        }
+
+        // Nothing was written
+        assert!(connection.into_inner().into_inner().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{Read, Write};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc::crc32;
+use errors::*;
+use super::{Transport, check_len, check_packet};
+
+/// Length of a packet around its payload
+///  - length: u32
+///  - seqno: i32
+///  - crc32: u32
+const OVERHEAD_LEN: usize = 4 + 4 + 4;
+
+/// The full framing; every packet carries its length, sequence number in the connection
+/// and a CRC32 of the rest of the packet
+// https://core.telegram.org/mtproto/mtproto-transports#full
+pub struct Full<S> {
+    stream: S,
+
+    /// Sequence number of the next packet to send
+    send_seqno: i32,
+
+    /// Sequence number of the next packet to receive
+    receive_seqno: i32,
+}
+
+impl<S: Read + Write> Full<S> {
+    pub fn new(stream: S) -> Full<S> {
+        Full {
+            stream: stream,
+            send_seqno: 0,
+            receive_seqno: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for Full<S> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        let mut buffer = Vec::with_capacity(OVERHEAD_LEN + packet.len());
+        buffer.write_u32::<LittleEndian>((OVERHEAD_LEN + packet.len()) as u32)?;
+        buffer.write_i32::<LittleEndian>(self.send_seqno)?;
+        buffer.write_all(packet)?;
+
+        let checksum = crc32::checksum_ieee(&buffer);
+        buffer.write_u32::<LittleEndian>(checksum)?;
+
+        self.stream.write_all(&buffer)?;
+        self.stream.flush()?;
+        self.send_seqno += 1;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let len = self.stream.read_u32::<LittleEndian>()? as usize;
+        if len < OVERHEAD_LEN {
+            return Err(ErrorKind::InvalidPacketLength(len).into());
+        }
+
+        check_len(len)?;
+
+        // The length is a part of the checksum
+        let mut buffer = vec![0; len];
+        LittleEndian::write_u32(&mut buffer, len as u32);
+        self.stream.read_exact(&mut buffer[4..])?;
+
+        let expected = LittleEndian::read_u32(&buffer[len - 4..]);
+        let checksum = crc32::checksum_ieee(&buffer[..len - 4]);
+        if checksum != expected {
+            return Err(ErrorKind::InvalidChecksum(expected, checksum).into());
+        }
+
+        let seqno = LittleEndian::read_i32(&buffer[4..]);
+        if seqno != self.receive_seqno {
+            return Err(ErrorKind::UnexpectedSeqNo(self.receive_seqno, seqno).into());
+        }
+
+        self.receive_seqno += 1;
+
+        let packet = buffer[8..len - 4].to_vec();
+        check_packet(&packet)?;
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use crc::crc32;
+    use errors::*;
+    use transport::{Transport, MAX_PACKET_LEN};
+    use super::Full;
+
+    /// A frame of the packet with its length, sequence number and checksum
+    fn frame(seqno: i32, packet: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.write_u32::<LittleEndian>(12 + packet.len() as u32).unwrap();
+        buffer.write_i32::<LittleEndian>(seqno).unwrap();
+        buffer.extend_from_slice(packet);
+
+        let checksum = crc32::checksum_ieee(&buffer);
+        buffer.write_u32::<LittleEndian>(checksum).unwrap();
+
+        buffer
+    }
+
+    /// A full connection the frames are received from
+    fn connection(frames: &[Vec<u8>]) -> Full<Cursor<Vec<u8>>> {
+        Full::new(Cursor::new(frames.concat()))
+    }
+
+    #[test]
+    fn send_frames() {
+        let mut connection = Full::new(Cursor::new(Vec::new()));
+        connection.send(&[1; 8]).unwrap();
+        connection.send(&[2; 16]).unwrap();
+
+        assert_eq!(connection.into_inner().into_inner(),
+                   [frame(0, &[1; 8]), frame(1, &[2; 16])].concat());
+    }
+
+    #[test]
+    fn receive_frames() {
+        let mut connection = connection(&[frame(0, &[1; 8]), frame(1, &[2; 16])]);
+
+        assert_eq!(connection.receive().unwrap(), [1; 8]);
+        assert_eq!(connection.receive().unwrap(), [2; 16]);
+    }
+
+    #[test]
+    fn reject_checksum() {
+        let mut tampered = frame(0, &[1; 8]);
+        tampered[10] ^= 1;
+
+        match connection(&[tampered]).receive() {
+            Err(Error(ErrorKind::InvalidChecksum(..), _)) => {}
+            result => panic!("expected InvalidChecksum; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reject_seqno() {
+        let mut connection = connection(&[frame(0, &[1; 8]), frame(2, &[2; 8])]);
+        connection.receive().unwrap();
+
+        match connection.receive() {
+            Err(Error(ErrorKind::UnexpectedSeqNo(1, 2), _)) => {}
+            result => panic!("expected UnexpectedSeqNo; found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reject_len() {
+        for &len in &[0, 8, 11, MAX_PACKET_LEN as u32 + 1, ::std::u32::MAX] {
+            let mut buffer = Vec::new();
+            buffer.write_u32::<LittleEndian>(len).unwrap();
+            buffer.extend_from_slice(&[0; 16]);
+
+            match Full::new(Cursor::new(buffer)).receive() {
+                Err(Error(ErrorKind::InvalidPacketLength(found), _)) => {
+                    assert_eq!(found, len as usize)
+                }
+                result => panic!("expected InvalidPacketLength; found {:?}", result),
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::str;
+use errors::*;
+use super::{Transport, check_len, check_packet};
+
+/// Most bytes the status line and headers of a response may take up
+const MAX_HEAD_LEN: usize = 8 * 1024;
+
+/// Packets POSTed to `http://<address>/api` over a kept-alive HTTP/1.1 connection; each request
+/// is answered with one response.
+///
+/// The server can only send to the client in a response; to receive messages initiated by
+/// the server (updates) send an encrypted `http_wait` and wait on its response (long polling)
+/// with `Http::long_poll`.
+// https://core.telegram.org/mtproto/mtproto-transports#http
+pub struct Http<S> {
+    stream: BufReader<S>,
+    host: String,
+
+    /// Number of requests sent and not yet answered
+    pending: usize,
+
+    /// Status line and headers of the response being read, as far as they were read
+    head: Vec<u8>,
+
+    /// Status, length and body of the response being read once its head was read; the body
+    /// as far as it was read
+    body: Option<(u16, usize, Vec<u8>)>,
+}
+
+impl Http<TcpStream> {
+    /// Connect to the server at the address (`149.154.167.50:80`)
+    pub fn connect(address: &str) -> Result<Http<TcpStream>> {
+        Ok(Http::new(TcpStream::connect(address)?, address))
+    }
+}
+
+impl<S: Read + Write> Http<S> {
+    /// Use an open connection; `host` is sent in the `Host` header of every request
+    pub fn new(stream: S, host: &str) -> Http<S> {
+        Http {
+            stream: BufReader::new(stream),
+            host: host.to_string(),
+            pending: 0,
+            head: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// Send an encrypted `http_wait` and wait on its response; the server holds the response
+    /// until it has messages to send or `max_wait` elapses
+    pub fn long_poll(&mut self, http_wait: &[u8]) -> Result<Vec<u8>> {
+        self.send(http_wait)?;
+        self.receive()
+    }
+
+    /// Read the status line and headers of a response; the status and the length of the body
+    /// are returned.
+    ///
+    /// What was read is kept if a read fails (times out) in the middle of a line; the next
+    /// call goes on from there.
+    fn read_head(&mut self) -> Result<(u16, usize)> {
+        // Up to the empty line after the headers
+        while !(self.head.ends_with(b"\n\r\n") || self.head.ends_with(b"\n\n")) {
+            let limit = (MAX_HEAD_LEN + 1 - self.head.len()) as u64;
+            if (&mut self.stream).take(limit).read_until(b'\n', &mut self.head)? == 0 {
+                return Err(ErrorKind::InvalidHttpResponse("unexpected end of headers").into());
+            }
+
+            if self.head.len() > MAX_HEAD_LEN {
+                return Err(ErrorKind::InvalidHttpResponse("headers too long").into());
+            }
+        }
+
+        let result = match str::from_utf8(&self.head) {
+            Ok(head) => parse_head(head),
+            Err(_) => Err(ErrorKind::InvalidHttpResponse("invalid headers").into()),
+        };
+
+        self.head.clear();
+        result
+    }
+}
+
+/// Parse the status line and headers of a response; the status and the length of the body
+/// are returned
+fn parse_head(head: &str) -> Result<(u16, usize)> {
+    let mut lines = head.lines();
+
+    // HTTP/1.1 200 OK
+    let line = lines.next().unwrap_or("");
+    let status = line.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok());
+    let status = match status {
+        Some(status) if line.starts_with("HTTP/1.") => status,
+        _ => return Err(ErrorKind::InvalidHttpResponse("invalid status line").into()),
+    };
+
+    let mut len = None;
+    for line in lines {
+        let header = line.trim_right();
+        if header.is_empty() {
+            break;
+        }
+
+        let mut s = header.splitn(2, ':');
+        let (name, value) = match (s.next(), s.next()) {
+            (Some(name), Some(value)) => (name.trim().to_lowercase(), value.trim()),
+            _ => return Err(ErrorKind::InvalidHttpResponse("invalid header").into()),
+        };
+
+        match &*name {
+            "content-length" => {
+                len = match value.parse() {
+                    Ok(len) => Some(len),
+                    Err(_) => {
+                        return Err(ErrorKind::InvalidHttpResponse("invalid Content-Length").into())
+                    }
+                }
+            }
+
+            "transfer-encoding" if !value.eq_ignore_ascii_case("identity") => {
+                return Err(ErrorKind::InvalidHttpResponse("unsupported Transfer-Encoding").into());
+            }
+
+            _ => {}
+        }
+    }
+
+    let len = match len {
+        Some(len) => len,
+        None => return Err(ErrorKind::InvalidHttpResponse("missing Content-Length").into()),
+    };
+
+    check_len(len)?;
+
+    Ok((status, len))
+}
+
+impl<S: Read + Write> Transport for Http<S> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        let mut buffer = Vec::with_capacity(packet.len() + 128);
+        write!(buffer,
+               "POST /api HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\nContent-Length: {}\r\n\r\n",
+               self.host,
+               packet.len())?;
+        buffer.write_all(packet)?;
+
+        let stream = self.stream.get_mut();
+        stream.write_all(&buffer)?;
+        stream.flush()?;
+        self.pending += 1;
+
+        Ok(())
+    }
+
+    /// Receive the response to the oldest request; an empty packet means the server had nothing
+    /// to send
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        if self.pending == 0 {
+            return Err(ErrorKind::NoPendingRequest.into());
+        }
+
+        if self.body.is_none() {
+            let (status, len) = self.read_head()?;
+            self.body = Some((status, len, Vec::with_capacity(len)));
+        }
+
+        // NOTE: The body of an error is read as well to keep the connection usable
+        if let Some((_, len, ref mut packet)) = self.body {
+            let missing = len - packet.len();
+            if (&mut self.stream).take(missing as u64).read_to_end(packet)? < missing {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of body")
+                    .into());
+            }
+        }
+
+        // The request is only answered once the whole response is read; after a failed read
+        // (a timeout) the rest of the response is still to come
+        let (status, _, packet) = self.body.take().unwrap();
+        self.pending -= 1;
+
+        if status != 200 {
+            return Err(ErrorKind::HttpStatus(status).into());
+        }
+
+        check_packet(&packet)?;
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use errors::*;
+    use transport::Transport;
+    use super::Http;
+
+    /// Read a request; its body is returned
+    fn read_request(stream: &mut BufReader<TcpStream>) -> Vec<u8> {
+        let mut len = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).unwrap();
+
+            if line.trim_right().is_empty() {
+                break;
+            }
+
+            if line.to_lowercase().starts_with("content-length:") {
+                len = line[15..].trim().parse().unwrap();
+            }
+        }
+
+        let mut request = vec![0; len];
+        stream.read_exact(&mut request).unwrap();
+
+        request
+    }
+
+    /// Read a request and answer it with the status and body given
+    fn answer(stream: &mut BufReader<TcpStream>, status: &str, body: &[u8]) -> Vec<u8> {
+        let request = read_request(stream);
+
+        let stream = stream.get_mut();
+        write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, body.len()).unwrap();
+        stream.write_all(body).unwrap();
+
+        request
+    }
+
+    /// A local HTTP server standing in for the API; `serve` answers on the accepted connection
+    fn connect<F>(serve: F) -> (Http<TcpStream>, thread::JoinHandle<()>)
+        where F: FnOnce(BufReader<TcpStream>) + Send + 'static
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve(BufReader::new(stream));
+        });
+
+        (Http::connect(&address).unwrap(), server)
+    }
+
+    #[test]
+    fn receive_in_order() {
+        let (mut http, server) = connect(|mut stream| {
+            for _ in 0..3 {
+                // Answer the first of two requests with nothing and echo the second one
+                // (with an error for the second packet)
+                let request = answer(&mut stream, "200 OK", &[]);
+                let status = if request[0] == 2 { "404 Not Found" } else { "200 OK" };
+                answer(&mut stream, status, &request);
+            }
+        });
+
+        for packet in &[[1u8; 8], [2; 8], [3; 8]] {
+            http.send(packet).unwrap();
+            http.send(packet).unwrap();
+
+            assert_eq!(http.receive().unwrap(), Vec::<u8>::new());
+            match http.receive() {
+                Ok(received) => assert_eq!(received, packet.to_vec()),
+                Err(Error(ErrorKind::HttpStatus(404), _)) => assert_eq!(packet[0], 2),
+                Err(err) => panic!("{}", err),
+            }
+        }
+
+        match http.receive() {
+            Err(Error(ErrorKind::NoPendingRequest, _)) => {}
+            _ => panic!("a response is received without a request"),
+        }
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn receive_after_timeout() {
+        let (answer_now, wait) = mpsc::channel();
+        let (mut http, server) = connect(move |mut stream| {
+            wait.recv().unwrap();
+            answer(&mut stream, "200 OK", &[1; 8]);
+        });
+
+        http.stream.get_ref().set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        http.send(&[0; 8]).unwrap();
+
+        // The request is still pending after the read timed out
+        assert!(http.receive().is_err());
+
+        answer_now.send(()).unwrap();
+        assert_eq!(http.receive().unwrap(), vec![1; 8]);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn receive_after_partial_timeout() {
+        let (answer_now, wait) = mpsc::channel();
+        let (mut http, server) = connect(move |mut stream| {
+            read_request(&mut stream);
+
+            // Cut in the middle of a line of the head and in the middle of the body
+            let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n".to_vec();
+            response.extend_from_slice(&[1; 8]);
+            for part in &[&response[..11], &response[11..30], &response[30..45], &response[45..]] {
+                wait.recv().unwrap();
+                stream.get_mut().write_all(part).unwrap();
+            }
+        });
+
+        http.stream.get_ref().set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        http.send(&[0; 8]).unwrap();
+
+        for _ in 0..3 {
+            answer_now.send(()).unwrap();
+            match http.receive() {
+                Err(Error(ErrorKind::Io(..), _)) => {}
+                result => panic!("expected a timeout; found {:?}", result),
+            }
+        }
+
+        answer_now.send(()).unwrap();
+        assert_eq!(http.receive().unwrap(), vec![1; 8]);
+
+        server.join().unwrap();
+    }
+}
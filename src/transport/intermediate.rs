@@ -0,0 +1,215 @@
+use std::io::{Read, Write};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use rand::{self, Rng};
+use errors::*;
+use super::{Framing, Transport, check_len, check_packet};
+
+/// Tag sent at the start of the connection (intermediate)
+const TAG: [u8; 4] = [0xee, 0xee, 0xee, 0xee];
+
+/// Tag sent at the start of the connection (padded intermediate)
+const PADDED_TAG: [u8; 4] = [0xdd, 0xdd, 0xdd, 0xdd];
+
+/// The intermediate framing; the length is 4 bytes
+// https://core.telegram.org/mtproto/mtproto-transports#intermediate
+pub struct Intermediate<S> {
+    stream: S,
+    tag_sent: bool,
+}
+
+impl<S: Read + Write> Intermediate<S> {
+    pub fn new(stream: S) -> Intermediate<S> {
+        Intermediate {
+            stream: stream,
+            tag_sent: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for Intermediate<S> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        let tag = if self.tag_sent { None } else { Some(&TAG) };
+        send(&mut self.stream, tag, packet, &[])?;
+        self.tag_sent = true;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        receive(&mut self.stream)
+    }
+}
+
+impl<S: Read + Write> Framing<S> for Intermediate<S> {
+    fn obfuscated_tag() -> [u8; 4] {
+        TAG
+    }
+
+    fn with_tag_sent(stream: S) -> Intermediate<S> {
+        Intermediate {
+            stream: stream,
+            tag_sent: true,
+        }
+    }
+}
+
+/// The padded intermediate framing; 0-15 random bytes follow each packet (and are counted
+/// in its length) so packet lengths are not distinguishable
+// https://core.telegram.org/mtproto/mtproto-transports#padded-intermediate
+pub struct PaddedIntermediate<S> {
+    stream: S,
+    tag_sent: bool,
+}
+
+impl<S: Read + Write> PaddedIntermediate<S> {
+    pub fn new(stream: S) -> PaddedIntermediate<S> {
+        PaddedIntermediate {
+            stream: stream,
+            tag_sent: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for PaddedIntermediate<S> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut padding = vec![0; rng.gen_range(0, 16)];
+        rng.fill_bytes(&mut padding);
+
+        let tag = if self.tag_sent { None } else { Some(&PADDED_TAG) };
+        send(&mut self.stream, tag, packet, &padding)?;
+        self.tag_sent = true;
+
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let mut packet = receive(&mut self.stream)?;
+
+        // The padding is only known from the message in the packet
+        //  - unencrypted: auth_key_id (0), message_id, message_data_length, message_data
+        //  - encrypted: auth_key_id, msg_key and a multiple of 16 bytes
+        let len = if packet.len() >= 20 && LittleEndian::read_i64(&packet) == 0 {
+            let data_len = LittleEndian::read_i32(&packet[16..]);
+            if data_len < 0 {
+                return Err(ErrorKind::InvalidPacketLength(packet.len()).into());
+            }
+
+            20 + data_len as usize
+        } else if packet.len() >= 24 {
+            packet.len() - (packet.len() - 24) % 16
+        } else {
+            packet.len()
+        };
+
+        if len > packet.len() {
+            return Err(ErrorKind::InvalidPacketLength(packet.len()).into());
+        }
+
+        packet.truncate(len);
+        Ok(packet)
+    }
+}
+
+impl<S: Read + Write> Framing<S> for PaddedIntermediate<S> {
+    fn obfuscated_tag() -> [u8; 4] {
+        PADDED_TAG
+    }
+
+    fn with_tag_sent(stream: S) -> PaddedIntermediate<S> {
+        PaddedIntermediate {
+            stream: stream,
+            tag_sent: true,
+        }
+    }
+}
+
+fn send<S: Write>(stream: &mut S, tag: Option<&[u8; 4]>, packet: &[u8], padding: &[u8]) -> Result<()> {
+    let mut buffer = Vec::with_capacity(8 + packet.len() + padding.len());
+    if let Some(tag) = tag {
+        buffer.write_all(tag)?;
+    }
+
+    buffer.write_u32::<LittleEndian>((packet.len() + padding.len()) as u32)?;
+    buffer.write_all(packet)?;
+    buffer.write_all(padding)?;
+
+    stream.write_all(&buffer)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+fn receive<S: Read>(stream: &mut S) -> Result<Vec<u8>> {
+    let len = stream.read_u32::<LittleEndian>()? as usize;
+    check_len(len)?;
+
+    let mut packet = vec![0; len];
+    stream.read_exact(&mut packet)?;
+    check_packet(&packet)?;
+
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use errors::*;
+    use transport::Transport;
+    use super::PaddedIntermediate;
+
+    /// A padded intermediate connection the packet (with its length) is received from
+    fn connection(packet: &[u8]) -> PaddedIntermediate<Cursor<Vec<u8>>> {
+        let mut buffer = Vec::new();
+        buffer.write_u32::<LittleEndian>(packet.len() as u32).unwrap();
+        buffer.extend_from_slice(packet);
+
+        PaddedIntermediate::new(Cursor::new(buffer))
+    }
+
+    /// An unencrypted message of the data length given followed by the padding
+    fn unencrypted(data_len: i32, data: &[u8], padding: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.write_i64::<LittleEndian>(0).unwrap();
+        packet.write_i64::<LittleEndian>(1).unwrap();
+        packet.write_i32::<LittleEndian>(data_len).unwrap();
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(padding);
+
+        packet
+    }
+
+    #[test]
+    fn strip_padding() {
+        let packet = unencrypted(8, &[1; 8], &[2; 5]);
+
+        assert_eq!(connection(&packet).receive().unwrap(), &packet[..28]);
+    }
+
+    #[test]
+    fn reject_negative_data_len() {
+        for &data_len in &[-1, -20, ::std::i32::MIN] {
+            match connection(&unencrypted(data_len, &[1; 8], &[2; 5])).receive() {
+                Err(Error(ErrorKind::InvalidPacketLength(..), _)) => {}
+                _ => panic!("a packet with a data length of {} is received", data_len),
+            }
+        }
+    }
+
+    #[test]
+    fn receive_error_code() {
+        match connection(&[0x6c, 0xfe, 0xff, 0xff]).receive() {
+            Err(Error(ErrorKind::TransportError(code), _)) => assert_eq!(code, -404),
+            _ => panic!("an error code is received as a packet"),
+        }
+    }
+}
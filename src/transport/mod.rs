@@ -0,0 +1,60 @@
+//! Transports carry whole MTProto packets (unencrypted or encrypted messages) to and from the server
+// https://core.telegram.org/mtproto/mtproto-transports
+
+use byteorder::{ByteOrder, LittleEndian};
+use errors::*;
+
+pub mod abridged;
+pub mod full;
+pub mod http;
+pub mod obfuscated2;
+pub mod intermediate;
+
+/// Largest packet accepted from the server
+pub const MAX_PACKET_LEN: usize = 16 * 1024 * 1024;
+
+pub trait Transport {
+    /// Send a packet
+    fn send(&mut self, packet: &[u8]) -> Result<()>;
+
+    /// Receive the next packet
+    fn receive(&mut self) -> Result<Vec<u8>>;
+}
+
+/// A TCP framing; identified by a tag at the start of the connection
+pub trait Framing<S>: Transport {
+    /// Tag carried in the header of an obfuscated connection
+    fn obfuscated_tag() -> [u8; 4];
+
+    /// Use a connection on which the tag of the framing was already sent
+    fn with_tag_sent(stream: S) -> Self;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&mut self, packet: &[u8]) -> Result<()> {
+        (**self).send(packet)
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        (**self).receive()
+    }
+}
+
+/// Check the length of a received packet; a packet of 4 bytes is an error code sent by the
+/// server in place of a message (`-404` when the authorization key is unknown)
+fn check_packet(packet: &[u8]) -> Result<()> {
+    if packet.len() == 4 {
+        return Err(ErrorKind::TransportError(LittleEndian::read_i32(packet)).into());
+    }
+
+    Ok(())
+}
+
+/// Check the length of a packet before reading it
+fn check_len(len: usize) -> Result<()> {
+    if len > MAX_PACKET_LEN {
+        return Err(ErrorKind::InvalidPacketLength(len).into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::io::{self, Read, Write};
+use crypto::aes::{self, KeySize};
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use rand::{self, Rng};
+use errors::*;
+use super::Framing;
+
+/// Length of the header sent at the start of the connection
+const HEADER_LEN: usize = 64;
+
+/// First 4 bytes the header must not start with; they would be mistaken for
+/// another protocol (HTTP, the plain TCP framings or TLS)
+const RESERVED_STARTS: [[u8; 4]; 7] = [*b"HEAD", *b"POST", *b"GET ", *b"OPTI",
+                                       [0xdd, 0xdd, 0xdd, 0xdd], [0xee, 0xee, 0xee, 0xee],
+                                       [0x16, 0x03, 0x01, 0x02]];
+
+/// A connection obfuscated with AES-256-CTR in both directions; the keys are derived from
+/// a random 64-byte header that also carries the tag of the framing used over it
+// https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation
+pub struct Obfuscated<S> {
+    stream: S,
+    encryptor: Box<SynchronousStreamCipher>,
+    decryptor: Box<SynchronousStreamCipher>,
+}
+
+impl<S: Read + Write> Obfuscated<S> {
+    /// Start the obfuscation by sending the header with the tag of the framing
+    pub fn new(mut stream: S, tag: [u8; 4]) -> Result<Obfuscated<S>> {
+        let mut header = random_header();
+        header[56..60].copy_from_slice(&tag);
+
+        // The key and IV of each direction are read from the header; forward to send
+        // and reversed to receive
+        let mut reversed = header[8..56].to_vec();
+        reversed.reverse();
+
+        let mut encryptor = aes::ctr(KeySize::KeySize256, &header[8..40], &header[40..56]);
+        let decryptor = aes::ctr(KeySize::KeySize256, &reversed[..32], &reversed[32..]);
+
+        // The header is sent as is except for the tag (and what follows) which is sent
+        // encrypted; the stream is encrypted from the start of the header on
+        let mut encrypted = [0; HEADER_LEN];
+        encryptor.process(&header, &mut encrypted);
+        header[56..].copy_from_slice(&encrypted[56..]);
+
+        stream.write_all(&header)?;
+        stream.flush()?;
+
+        Ok(Obfuscated {
+            stream: stream,
+            encryptor: encryptor,
+            decryptor: decryptor,
+        })
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read> Read for Obfuscated<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.stream.read(buf)?;
+
+        let encrypted = buf[..len].to_vec();
+        self.decryptor.process(&encrypted, &mut buf[..len]);
+
+        Ok(len)
+    }
+}
+
+impl<S: Write> Write for Obfuscated<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // NOTE: Everything is written as the counter advances with each byte encrypted
+        let mut encrypted = vec![0; buf.len()];
+        self.encryptor.process(buf, &mut encrypted);
+        self.stream.write_all(&encrypted)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Obfuscate the connection and use the framing over it (`Abridged`, `Intermediate` or
+/// `PaddedIntermediate`)
+///
+/// ```rust,ignore
+/// let transport: Intermediate<_> = obfuscated2::connect(TcpStream::connect(address)?)?;
+/// ```
+pub fn connect<S, F>(stream: S) -> Result<F>
+    where S: Read + Write,
+          F: Framing<Obfuscated<S>>
+{
+    let stream = Obfuscated::new(stream, F::obfuscated_tag())?;
+    Ok(F::with_tag_sent(stream))
+}
+
+fn random_header() -> [u8; HEADER_LEN] {
+    let mut rng = rand::thread_rng();
+    let mut header = [0; HEADER_LEN];
+
+    loop {
+        rng.fill_bytes(&mut header);
+
+        // The abridged tag (0xef) as the first byte and a zero second word are also reserved
+        if header[0] != 0xef && header[4..8] != [0; 4] &&
+           !RESERVED_STARTS.iter().any(|start| header[..4] == start[..]) {
+            return header;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+    use crypto::aes::{self, KeySize};
+    use super::{random_header, Obfuscated, HEADER_LEN};
+
+    /// A connection that receives what was put in `input` and keeps what is sent in `output`
+    #[derive(Default)]
+    struct Loopback {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn avoid_reserved_headers() {
+        let reserved: [&[u8]; 6] = [&[0xef], b"HEAD", b"POST", b"GET ", &[0xee; 4], &[0xdd; 4]];
+
+        for _ in 0..10000 {
+            let header = random_header();
+
+            assert!(!reserved.iter().any(|start| header.starts_with(start)),
+                    "header starts with {:?}",
+                    &header[..4]);
+            assert!(header[4..8] != [0; 4]);
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        let tag = [0xee; 4];
+        let mut client = Obfuscated::new(Loopback::default(), tag).unwrap();
+        client.write_all(b"ping").unwrap();
+
+        let sent = client.stream.output.clone();
+        assert_eq!(sent.len(), HEADER_LEN + 4);
+
+        // The server reads the keys from the header; forward to receive and reversed to send
+        let mut reversed = sent[8..56].to_vec();
+        reversed.reverse();
+
+        let mut decryptor = aes::ctr(KeySize::KeySize256, &sent[8..40], &sent[40..56]);
+        let mut encryptor = aes::ctr(KeySize::KeySize256, &reversed[..32], &reversed[32..]);
+
+        let mut received = vec![0; sent.len()];
+        decryptor.process(&sent, &mut received);
+        assert_eq!(received[56..60], tag);
+        assert_eq!(&received[HEADER_LEN..], b"ping");
+
+        let mut answer = [0; 4];
+        encryptor.process(b"pong", &mut answer);
+        client.stream.input = Cursor::new(answer.to_vec());
+
+        let mut buffer = [0; 4];
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"pong");
+    }
+}
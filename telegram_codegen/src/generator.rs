@@ -5,6 +5,7 @@ use std::io::Write;
 use std::error::Error;
 
 struct Constructor {
+    id: i32,
     name: String,
     params: Vec<Parameter>,
 }
@@ -14,13 +15,25 @@ struct Type {
     constructors: Vec<Constructor>,
 }
 
+struct Method {
+    id: i32,
+    name: String,
+    params: Vec<Parameter>,
+    kind: String,
+}
+
 #[derive(Default)]
 struct Module {
     types: HashMap<String, Type>,
+    methods: HashMap<String, Method>,
 }
 
 fn translate_typename(typename: &str, current_module: &Option<String>) -> String {
-    if typename.contains("Vector<") {
+    if typename.starts_with('%') {
+        // NOTE: A bare type (`%Message`) is the same Rust type as the boxed type
+        translate_typename(&typename[1..], current_module)
+    } else if typename.starts_with("Vector<") || typename.starts_with("vector<") {
+        // NOTE: A bare `vector` is distinguished by the serialized name of the field
         let s = typename.split(|c| c == '<' || c == '>').collect::<Vec<_>>();
         let typename = translate_typename(s[1], current_module);
 
@@ -34,7 +47,12 @@ fn translate_typename(typename: &str, current_module: &Option<String>) -> String
             }
         }
 
-        format!("::{}::{}", s[0], s[1])
+        if current_module.is_some() {
+            // Reference a sibling module from within a module
+            return format!("super::{}::{}", s[0], s[1]);
+        }
+
+        format!("{}::{}", s[0], s[1])
     } else {
         match typename {
             // Primitive conversion
@@ -45,10 +63,35 @@ fn translate_typename(typename: &str, current_module: &Option<String>) -> String
             "Vec<long>" => "Vec<i64>".to_string(),
             "long" => "i64".to_string(),
             "double" => "f64".to_string(),
-            "bytes" => "Vec<u8>".to_string(),
+            "int128" => "::tl::Int128".to_string(),
+            "int256" => "::tl::Int256".to_string(),
+            // A boxed object of any type is kept serialized
+            "Object" => "::tl::Object".to_string(),
+            // NOTE: `Vec<u8>` would be serialized as a sequence
+            "bytes" => "::serde::bytes::ByteBuf".to_string(),
+
+            _ => {
+                if current_module.is_some() {
+                    // Reference the root of the schema from within a module
+                    format!("super::{}", typename)
+                } else {
+                    typename.to_string()
+                }
+            }
+        }
+    }
+}
 
-            _ => format!("::{}", typename)
+fn translate_param_typename(typename: &str, current_module: &Option<String>) -> String {
+    if let Some(index) = typename.find('?') {
+        // A conditional parameter (`flags.N?T`) is optional
+        // The `true` type is only ever a set bit in the flags
+        match &typename[index + 1..] {
+            "true" => "bool".to_string(),
+            typename => format!("Option<{}>", translate_typename(typename, current_module)),
         }
+    } else {
+        translate_typename(typename, current_module)
     }
 }
 
@@ -77,24 +120,24 @@ fn translate_id(id: &str, current_module: &Option<String>) -> String {
 pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
     let mut modules = HashMap::<Option<String>, Module>::new();
 
+    // Types of the constructors; a constructor may be referenced as a bare type
+    // NOTE: `true` (`flags.N?true`) and the other primitive constructors are not
+    let kinds = schema.constructors
+        .iter()
+        .filter(|constructor| !is_primitive(&constructor.kind))
+        .map(|constructor| (constructor.predicate.as_str(), constructor.kind.as_str()))
+        .collect::<HashMap<_, _>>();
+
     // Translate raw parse
     for constructor in &schema.constructors {
         // Recognized primitive types are ignored when defined
         // and raised to the associated Rust primitive type when requested
         //  - Bool => bool
-        //  - True => true (wtf is this; it's not used anywhere in the schema)
+        //  - True => bool (only ever used as a set bit in the flags; `flags.N?true`)
         //  - Vector t => Vec<T>
         //  - Null => ? (figure out what to do with this)
-        // TODO(@rust): Is there a clean way to check against a constant set ?
-        if constructor.kind == "Bool" || constructor.kind == "True" ||
-           constructor.kind == "Vector t" || constructor.kind == "Null" {
-            continue;
-        }
-
-        // Check for exceptions
-        if constructor.kind == "PeerSettings" {
-            // 1 - PeerSettings doesn't seem to exist (along with the associated method) but its still in the
-            //     schema with a seemingly illegal definition
+        //  - Object => tl::Object (`gzip_packed` is the only constructor and is not a type of its own)
+        if is_primitive(&constructor.kind) {
             continue;
         }
 
@@ -108,8 +151,9 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
 
         // Translate
         let c = Constructor {
+            id: constructor.id,
             name: constructor.predicate.clone(),
-            params: constructor.params.clone(),
+            params: resolve_bare_constructors(&constructor.params, &kinds),
         };
 
         // Build up type in module
@@ -118,6 +162,43 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
         type_.constructors.push(c);
     }
 
+    for method in &schema.methods {
+        // Split method into <module>.<name>
+        let s = method.method.splitn(2, '.').collect::<Vec<_>>();
+        let (module, name) = if s.len() == 1 {
+            (None, s[0])
+        } else {
+            (Some(s[0].to_string()), s[1])
+        };
+
+        // Methods returning a type that is not generated (`http_wait`) are never answered
+        // and are not a `RemoteCall`
+        let kind = if method.kind == "X" || method.kind == "Bool" || method.kind.contains("Vector<") ||
+                      is_generated(&modules, &method.kind) {
+            method.kind.clone()
+        } else {
+            String::new()
+        };
+
+        let ref mut module_ = modules.entry(module).or_insert_with(Default::default);
+
+        // Methods are named as types; `auth.sendCode` => `auth::SendCode`
+        let mut name = translate_method_name(name);
+        if module_.types.contains_key(&name) {
+            // Avoid a collision with a type of the same name (`rpc_drop_answer` => `RpcDropAnswer`)
+            name.push_str("Request");
+        }
+
+        let m = Method {
+            id: method.id,
+            name: method.method.clone(),
+            params: resolve_bare_constructors(&method.params, &kinds),
+            kind: kind,
+        };
+
+        module_.methods.insert(name, m);
+    }
+
     // Output buffered information
     let mut f = File::create(filename).unwrap();
     for (module_name, module) in &modules {
@@ -132,9 +213,13 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
             // Open type
             if type_.constructors.len() == 1 {
                 // A single constructor is output as a struct
+                // The serialized name carries the constructor ID (`predicate#id`)
+                writeln!(f, "#[serde(rename = \"{}\")]", wire_name(&type_.constructors[0].name, type_.constructors[0].id))?;
+
                 if type_.constructors[0].params.len() == 0 {
                     // A single constructor with no parameters is a unit
-                    writeln!(f, "pub struct {};", name)?;
+                    writeln!(f, "pub struct {};\n", name)?;
+                    write_identifiable(&mut f, name, type_, module_name)?;
                     continue;
                 } else {
                     writeln!(f, "pub struct {} {{", name)?;
@@ -146,6 +231,11 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
             for constructor in &type_.constructors {
                 let constructor_name = translate_id(&constructor.name, module_name);
 
+                if type_.constructors.len() > 1 {
+                    // The serialized name of each variant carries its constructor ID
+                    writeln!(f, "  #[serde(rename = \"{}\")]", wire_name(&constructor.name, constructor.id))?;
+                }
+
                 if constructor.params.len() == 0 {
                     // No parameters
                     writeln!(f, "  {},", constructor_name)?;
@@ -156,12 +246,9 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
                     }
 
                     // Write out parameters
-                    for param in &constructor.params {
-                        writeln!(f,
-                                 "    {}: {},",
-                                 translate_id(&param.name, module_name),
-                                 translate_typename(&param.kind, module_name))?;
-                    }
+                    // NOTE: Fields of enum variants are public by definition
+                    let visibility = if type_.constructors.len() > 1 { "" } else { "pub " };
+                    write_params(&mut f, &constructor.params, visibility, module_name)?;
 
                     // Close constructor (if more than 1)
                     if type_.constructors.len() > 1 {
@@ -172,6 +259,12 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
 
             // Close type
             writeln!(f, "}}\n")?;
+
+            write_identifiable(&mut f, name, type_, module_name)?;
+        }
+
+        for (name, method) in &module.methods {
+            write_method(&mut f, name, method, module_name)?;
         }
 
         if module_name.is_some() {
@@ -182,3 +275,216 @@ pub fn generate(filename: &str, schema: Schema) -> Result<(), Box<Error>> {
 
     Ok(())
 }
+
+fn is_primitive(kind: &str) -> bool {
+    match kind {
+        "Bool" | "True" | "Vector t" | "Null" | "Object" => true,
+        _ => false,
+    }
+}
+
+/// Refer to bare constructors (`vector<future_salt>`) by their bare type (`vector<%FutureSalt>`)
+fn resolve_bare_constructors(params: &[Parameter], kinds: &HashMap<&str, &str>) -> Vec<Parameter> {
+    params.iter()
+        .map(|param| {
+            // flags.0?vector<future_salt> => (flags.0?vector<, future_salt, >)
+            let start = param.kind.rfind(|c| c == '?' || c == '<').map_or(0, |index| index + 1);
+            let end = param.kind[start..].find('>').map_or(param.kind.len(), |index| start + index);
+
+            let kind = match kinds.get(&param.kind[start..end]) {
+                Some(kind) => {
+                    format!("{}%{}{}", &param.kind[..start], kind, &param.kind[end..])
+                }
+
+                None => param.kind.clone(),
+            };
+
+            Parameter {
+                name: param.name.clone(),
+                kind: kind,
+            }
+        })
+        .collect()
+}
+
+/// Name a constructor or method is serialized with; `predicate#id` as it would appear in
+/// the TL schema
+fn wire_name(name: &str, id: i32) -> String {
+    format!("{}#{:08x}", name, id as u32)
+}
+
+/// Check if a type (`<module>.<name>`) was generated from the constructors in the schema
+fn is_generated(modules: &HashMap<Option<String>, Module>, typename: &str) -> bool {
+    let s = typename.splitn(2, '.').collect::<Vec<_>>();
+    let (module, name) = if s.len() == 1 {
+        (None, s[0])
+    } else {
+        (Some(s[0].to_string()), s[1])
+    };
+
+    modules.get(&module).map_or(false, |module| module.types.contains_key(name))
+}
+
+fn translate_method_name(name: &str) -> String {
+    // sendCode => SendCode, req_DH_params => ReqDHParams
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Write out the parameters of a constructor or method as fields
+fn write_params(f: &mut File,
+                params: &[Parameter],
+                visibility: &str,
+                current_module: &Option<String>)
+                -> Result<(), Box<Error>> {
+    // A flags word (`flags:#`) is not a field; it is computed from the conditional fields
+    // (`flags.N?T`) and declared in the serialized name of the field that follows
+    let mut declarations = Vec::new();
+
+    for (index, param) in params.iter().enumerate() {
+        if param.kind == "#" {
+            declarations.push(format!("{}:#", param.name));
+            continue;
+        }
+
+        let kind = if param.kind.starts_with('!') {
+            // Generic parameter of a method
+            param.kind[1..].to_string()
+        } else {
+            translate_param_typename(&param.kind, current_module)
+        };
+
+        // An `Object` right after its length (`bytes:int body:Object` in `message`) only takes
+        // up that many bytes; it is declared along with the field it is as long as
+        // (`body:Object[bytes]`)
+        let length = match index.checked_sub(1).map(|index| &params[index]) {
+            Some(previous) if param.kind == "Object" && previous.name == "bytes" &&
+                              previous.kind == "int" => Some(&previous.name),
+            _ => None,
+        };
+
+        if !declarations.is_empty() || param.kind.contains('?') || param.kind.contains("vector<") ||
+           param.kind.starts_with('%') || length.is_some() {
+            // The serialized name of the field is its TL declaration (`flags:# title:flags.3?string`)
+            match length {
+                Some(length) => declarations.push(format!("{}:{}[{}]", param.name, param.kind, length)),
+                None => declarations.push(format!("{}:{}", param.name, param.kind)),
+            }
+            writeln!(f, "    #[serde(rename = \"{}\")]", declarations.join(" "))?;
+            declarations.clear();
+        }
+
+        writeln!(f,
+                 "    {}{}: {},",
+                 visibility,
+                 translate_id(&param.name, current_module),
+                 kind)?;
+    }
+
+    if !declarations.is_empty() {
+        return Err(From::from(format!("no parameter follows `{}`", declarations.join(" "))));
+    }
+
+    Ok(())
+}
+
+/// Write out a method as a request struct along with its implementation of `RemoteCall`
+fn write_method(f: &mut File,
+                name: &str,
+                method: &Method,
+                current_module: &Option<String>)
+                -> Result<(), Box<Error>> {
+    // A generic method (`invokeWithLayer {X:Type} query:!X = X`) wraps another method
+    // and is answered with its return type
+    let generic = method.params
+        .iter()
+        .find(|param| param.kind.starts_with('!'))
+        .map(|param| param.kind[1..].to_string());
+
+    let (type_name, impl_generics) = match generic {
+        Some(ref generic) => (format!("{}<{}>", name, generic), format!("<{}>", generic)),
+        None => (name.to_string(), String::new()),
+    };
+
+    writeln!(f, "#[derive(Debug, Deserialize, Serialize)]")?;
+    writeln!(f, "#[serde(rename = \"{}\")]", wire_name(&method.name, method.id))?;
+
+    if method.params.len() == 0 {
+        writeln!(f, "pub struct {};\n", type_name)?;
+    } else {
+        writeln!(f, "pub struct {} {{", type_name)?;
+        write_params(f, &method.params, "pub ", current_module)?;
+        writeln!(f, "}}\n")?;
+    }
+
+    writeln!(f, "impl{} ::tl::Identifiable for {} {{", impl_generics, type_name)?;
+    writeln!(f, "  fn constructor_id(&self) -> u32 {{")?;
+    writeln!(f, "    0x{:08x}", method.id as u32)?;
+    writeln!(f, "  }}")?;
+    writeln!(f, "}}\n")?;
+
+    if !method.kind.is_empty() {
+        match generic {
+            Some(ref generic) if *generic == method.kind => {
+                writeln!(f,
+                         "impl<{0}: ::tl::RemoteCall> ::tl::RemoteCall for {1} {{",
+                         generic,
+                         type_name)?;
+                writeln!(f, "  type Return = {}::Return;", generic)?;
+            }
+
+            _ => {
+                writeln!(f, "impl{} ::tl::RemoteCall for {} {{", impl_generics, type_name)?;
+                writeln!(f,
+                         "  type Return = {};",
+                         translate_typename(&method.kind, current_module))?;
+            }
+        }
+
+        writeln!(f, "}}\n")?;
+    }
+
+    Ok(())
+}
+
+/// Write out the implementation of `Identifiable` for a type
+fn write_identifiable(f: &mut File,
+                      name: &str,
+                      type_: &Type,
+                      current_module: &Option<String>)
+                      -> Result<(), Box<Error>> {
+    writeln!(f, "impl ::tl::Identifiable for {} {{", name)?;
+    writeln!(f, "  fn constructor_id(&self) -> u32 {{")?;
+
+    if type_.constructors.len() == 1 {
+        writeln!(f, "    0x{:08x}", type_.constructors[0].id as u32)?;
+    } else {
+        writeln!(f, "    match *self {{")?;
+
+        for constructor in &type_.constructors {
+            let constructor_name = translate_id(&constructor.name, current_module);
+            let pattern = if constructor.params.len() == 0 { "" } else { " { .. }" };
+
+            writeln!(f,
+                     "      {}::{}{} => 0x{:08x},",
+                     name,
+                     constructor_name,
+                     pattern,
+                     constructor.id as u32)?;
+        }
+
+        writeln!(f, "    }}")?;
+    }
+
+    writeln!(f, "  }}")?;
+    writeln!(f, "}}\n")?;
+
+    Ok(())
+}
@@ -4,9 +4,11 @@ extern crate serde;
 extern crate serde_derive;
 
 extern crate serde_json;
+extern crate crc;
 
 mod parser;
 mod generator;
+mod tl;
 
 use std::fs::File;
 use std::io::Read;
@@ -18,7 +20,12 @@ pub fn translate(input_filename: &str, output_filename: &str) -> Result<(), Box<
     let mut s = String::new();
     f.read_to_string(&mut s)?;
 
-    let s: parser::Schema = s.parse()?;
+    // The schema is either the JSON export or TL itself
+    let s: parser::Schema = if input_filename.ends_with(".tl") {
+        tl::parse(&s)?
+    } else {
+        s.parse()?
+    };
 
     generator::generate(output_filename, s)?;
 
@@ -29,7 +29,7 @@ pub struct Method {
     pub kind: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct Schema {
     pub constructors: Vec<Constructor>,
     pub methods: Vec<Method>,
@@ -0,0 +1,424 @@
+use std::error::Error;
+use std::fmt;
+use crc::crc32;
+use parser::{Schema, Constructor, Method, Parameter};
+
+/// Error raised when a TL schema could not be parsed
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl ParseError {
+    fn new<T: Into<String>>(line: usize, message: T) -> ParseError {
+        ParseError {
+            line: line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Types,
+    Functions,
+}
+
+/// Parse a schema written in the TL language (`.tl`)
+// https://core.telegram.org/mtproto/TL
+pub fn parse(s: &str) -> Result<Schema, ParseError> {
+    let mut schema = Schema {
+        constructors: Vec::new(),
+        methods: Vec::new(),
+    };
+
+    let mut section = Section::Types;
+
+    // A declaration may span several lines and a line may hold several declarations
+    let mut declaration = String::new();
+    let mut declaration_line = 0;
+
+    for (index, line) in strip_comments(s).lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with("---") {
+            if !declaration.is_empty() {
+                return Err(ParseError::new(declaration_line, "unterminated declaration"));
+            }
+
+            section = match line {
+                "---types---" => Section::Types,
+                "---functions---" => Section::Functions,
+                _ => {
+                    return Err(ParseError::new(index + 1, format!("unknown section `{}`", line)))
+                }
+            };
+
+            continue;
+        }
+
+        if declaration.is_empty() {
+            declaration_line = index + 1;
+        } else {
+            declaration.push(' ');
+        }
+
+        declaration.push_str(line);
+
+        while let Some(end) = declaration.find(';') {
+            parse_declaration(&declaration[..end], section, declaration_line, &mut schema)?;
+
+            declaration = declaration[end + 1..].trim().to_string();
+            declaration_line = index + 1;
+        }
+    }
+
+    if !declaration.is_empty() {
+        return Err(ParseError::new(declaration_line, "unterminated declaration"));
+    }
+
+    Ok(schema)
+}
+
+/// Replace `// ..` and `/* .. */` comments with whitespace; line breaks are kept
+/// so errors can refer to the original line
+fn strip_comments(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().cloned()) {
+            ('/', Some('/')) => {
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+
+                    chars.next();
+                }
+            }
+
+            ('/', Some('*')) => {
+                chars.next();
+
+                let mut last = ' ';
+                while let Some(c) = chars.next() {
+                    if c == '\n' {
+                        result.push('\n');
+                    } else if last == '*' && c == '/' {
+                        break;
+                    }
+
+                    last = c;
+                }
+            }
+
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Parse a single combinator declaration (without the trailing `;`)
+///  - `inputPeerUser#7b8e7de6 user_id:int access_hash:long = InputPeer`
+///  - `invokeWithLayer#da9b0d0d {X:Type} layer:int query:!X = X`
+fn parse_declaration(declaration: &str,
+                     section: Section,
+                     line: usize,
+                     schema: &mut Schema)
+                     -> Result<(), ParseError> {
+    let s = declaration.splitn(2, '=').collect::<Vec<_>>();
+    if s.len() != 2 {
+        return Err(ParseError::new(line, "expected `=` in declaration"));
+    }
+
+    let kind = s[1].split_whitespace().collect::<Vec<_>>().join(" ");
+    if kind.is_empty() {
+        return Err(ParseError::new(line, "expected a result type after `=`"));
+    }
+
+    let mut tokens = s[0].split_whitespace();
+    let head = match tokens.next() {
+        Some(head) => head,
+        None => return Err(ParseError::new(line, "expected a combinator name")),
+    };
+
+    let (name, id) = match head.find('#') {
+        Some(index) => {
+            let id = u32::from_str_radix(&head[index + 1..], 16)
+                .map_err(|_| ParseError::new(line, format!("invalid constructor ID in `{}`", head)))?;
+
+            (&head[..index], Some(id))
+        }
+
+        None => (head, None),
+    };
+
+    if name.is_empty() {
+        return Err(ParseError::new(line, "expected a combinator name"));
+    }
+
+    let tokens = tokens.collect::<Vec<_>>();
+    let mut params = Vec::new();
+    let mut repeated = false;
+
+    for token in &tokens {
+        if *token == "?" || token.contains("*[") {
+            // Built-in types (`int ? = Int`, `int128 4*[ int ] = Int128`) are not
+            // a part of the schema
+            return Ok(());
+        }
+
+        if token.starts_with('{') {
+            // Generic type arguments (`{X:Type}`) are implied by the `!X` parameters
+            continue;
+        }
+
+        if *token == "[" {
+            repeated = true;
+            continue;
+        }
+
+        if *token == "]" {
+            repeated = false;
+            continue;
+        }
+
+        if repeated || *token == "#" {
+            // Anonymous parameters only appear in the built-in `vector`
+            continue;
+        }
+
+        let s = token.splitn(2, ':').collect::<Vec<_>>();
+        if s.len() != 2 || s[0].is_empty() || s[1].is_empty() {
+            return Err(ParseError::new(line, format!("invalid parameter `{}`", token)));
+        }
+
+        params.push(Parameter {
+            name: s[0].to_string(),
+            kind: s[1].to_string(),
+        });
+    }
+
+    if repeated {
+        return Err(ParseError::new(line, "unterminated `[`"));
+    }
+
+    let id = match id {
+        Some(id) => id,
+        None => compute_id(name, &tokens, &kind),
+    };
+
+    match section {
+        Section::Types => {
+            schema.constructors.push(Constructor {
+                id: id as i32,
+                predicate: name.to_string(),
+                params: params,
+                kind: kind,
+            })
+        }
+
+        Section::Functions => {
+            schema.methods.push(Method {
+                id: id as i32,
+                method: name.to_string(),
+                params: params,
+                kind: kind,
+            })
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the ID of a combinator declared without one; the CRC32 of the normalized declaration
+fn compute_id(name: &str, tokens: &[&str], kind: &str) -> u32 {
+    let mut s = name.to_string();
+
+    for token in tokens {
+        // Parameters of type `true` only exist in the flags and are not a part of the ID
+        if token.contains("?true") {
+            continue;
+        }
+
+        // `bytes` is an alias of `string`
+        let token = if token.ends_with(":bytes") || token.ends_with("?bytes") {
+            format!("{}string", &token[..token.len() - "bytes".len()])
+        } else {
+            token.to_string()
+        };
+
+        s.push(' ');
+        s.push_str(&token);
+    }
+
+    s.push_str(" = ");
+    s.push_str(kind);
+
+    // Generic arguments are written without brackets (`Vector<long>` => `Vector long`)
+    let s = s.replace('<', " ").replace('>', "").replace('{', "").replace('}', "");
+
+    crc32::checksum_ieee(s.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::{Parameter, Schema};
+    use super::parse;
+
+    fn params(params: &[Parameter]) -> Vec<(&str, &str)> {
+        params.iter().map(|param| (&*param.name, &*param.kind)).collect()
+    }
+
+    fn constructor_ids(schema: &Schema) -> Vec<(&str, u32)> {
+        schema.constructors.iter().map(|c| (&*c.predicate, c.id as u32)).collect()
+    }
+
+    #[test]
+    fn keep_given_ids() {
+        let schema = parse("inputPeerUser#7b8e7de6 user_id:int access_hash:long = InputPeer;\n\
+                            resPQ#05162463 nonce:int128 server_nonce:int128 pq:bytes \
+                            server_public_key_fingerprints:Vector<long> = ResPQ;")
+            .unwrap();
+
+        assert_eq!(constructor_ids(&schema),
+                   vec![("inputPeerUser", 0x7b8e7de6), ("resPQ", 0x05162463)]);
+        assert_eq!(params(&schema.constructors[1].params),
+                   vec![("nonce", "int128"),
+                        ("server_nonce", "int128"),
+                        ("pq", "bytes"),
+                        ("server_public_key_fingerprints", "Vector<long>")]);
+        assert_eq!(schema.constructors[1].kind, "ResPQ");
+    }
+
+    #[test]
+    fn compute_missing_ids() {
+        // `bytes` is computed as `string` and `Vector<long>` as `Vector long`
+        let schema = parse("inputPeerUser user_id:int access_hash:long = InputPeer;\n\
+                            resPQ nonce:int128 server_nonce:int128 pq:bytes \
+                            server_public_key_fingerprints:Vector<long> = ResPQ;")
+            .unwrap();
+
+        assert_eq!(constructor_ids(&schema),
+                   vec![("inputPeerUser", 0x7b8e7de6), ("resPQ", 0x05162463)]);
+    }
+
+    #[test]
+    fn parse_generics() {
+        let schema = parse("---functions---\n\
+                            invokeWithLayer {X:Type} layer:int query:!X = X;\n\
+                            invokeAfterMsg#cb9f372d {X:Type} msg_id:long query:!X = X;")
+            .unwrap();
+
+        assert!(schema.constructors.is_empty());
+        assert_eq!(schema.methods.len(), 2);
+
+        let method = &schema.methods[0];
+        assert_eq!((&*method.method, method.id as u32), ("invokeWithLayer", 0xda9b0d0d));
+        assert_eq!(params(&method.params), vec![("layer", "int"), ("query", "!X")]);
+        assert_eq!(method.kind, "X");
+
+        let method = &schema.methods[1];
+        assert_eq!((&*method.method, method.id as u32), ("invokeAfterMsg", 0xcb9f372d));
+        assert_eq!(params(&method.params), vec![("msg_id", "long"), ("query", "!X")]);
+    }
+
+    #[test]
+    fn parse_flags() {
+        // Parameters of type `true` are left out of the computed ID but not of the parameters
+        let schema = parse("peerSettings flags:# report_spam:flags.0?true = PeerSettings;\n\
+                            messageFwdHeader flags:# from_id:flags.0?int date:int \
+                            channel_id:flags.1?int channel_post:flags.2?int = MessageFwdHeader;\n\
+                            updateShortMessage flags:# out:flags.1?true mentioned:flags.4?true \
+                            media_unread:flags.5?true silent:flags.13?true id:int user_id:int \
+                            message:string pts:int pts_count:int date:int \
+                            fwd_from:flags.2?MessageFwdHeader via_bot_id:flags.11?int \
+                            reply_to_msg_id:flags.3?int entities:flags.7?Vector<MessageEntity> \
+                            = Updates;")
+            .unwrap();
+
+        assert_eq!(constructor_ids(&schema),
+                   vec![("peerSettings", 0x818426cd),
+                        ("messageFwdHeader", 0xc786ddcb),
+                        ("updateShortMessage", 0x914fbf11)]);
+
+        assert_eq!(params(&schema.constructors[0].params),
+                   vec![("flags", "#"), ("report_spam", "flags.0?true")]);
+        assert_eq!(params(&schema.constructors[1].params),
+                   vec![("flags", "#"),
+                        ("from_id", "flags.0?int"),
+                        ("date", "int"),
+                        ("channel_id", "flags.1?int"),
+                        ("channel_post", "flags.2?int")]);
+        assert_eq!(params(&schema.constructors[2].params[11..]),
+                   vec![("fwd_from", "flags.2?MessageFwdHeader"),
+                        ("via_bot_id", "flags.11?int"),
+                        ("reply_to_msg_id", "flags.3?int"),
+                        ("entities", "flags.7?Vector<MessageEntity>")]);
+    }
+
+    #[test]
+    fn parse_sections() {
+        let schema = parse("---types---\n\
+                            pong#347773c5 msg_id:long ping_id:long = Pong;\n\
+                            ---functions---\n\
+                            ping#7abe77ec ping_id:long = Pong;\n\
+                            ---types---\n\
+                            future_salt#0949d9dc valid_since:int valid_until:int salt:long \
+                            = FutureSalt;")
+            .unwrap();
+
+        assert_eq!(constructor_ids(&schema),
+                   vec![("pong", 0x347773c5), ("future_salt", 0x0949d9dc)]);
+        assert_eq!(schema.methods.len(), 1);
+        assert_eq!((&*schema.methods[0].method, schema.methods[0].id as u32),
+                   ("ping", 0x7abe77ec));
+        assert_eq!(schema.methods[0].kind, "Pong");
+
+        assert!(parse("---methods---\n").is_err());
+        assert!(parse("ping ping_id:long\n---functions---\n").is_err());
+    }
+
+    #[test]
+    fn skip_comments() {
+        let schema = parse("// Built-in types are not a part of the schema\n\
+                            int ? = Int; // int\n\
+                            /* A declaration\n   across lines */ pong#347773c5\n\
+                            msg_id:long // the message answered\n\
+                            ping_id:long = Pong; ping#7abe77ec ping_id:long = Pong;\n\
+                            /* dropped#00000001 = Dropped; */")
+            .unwrap();
+
+        assert_eq!(constructor_ids(&schema),
+                   vec![("pong", 0x347773c5), ("ping", 0x7abe77ec)]);
+        assert_eq!(params(&schema.constructors[0].params),
+                   vec![("msg_id", "long"), ("ping_id", "long")]);
+    }
+
+    #[test]
+    fn report_line() {
+        let error = parse("/* A comment\n   across lines */\n\
+                           pong#347773c5 msg_id:long ping_id = Pong;")
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "line 3: invalid parameter `ping_id`");
+    }
+}